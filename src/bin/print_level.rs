@@ -1,39 +1,38 @@
-use std::env;
 use std::io::stdout;
-use rustydave::{generate_level, Tile, LEVEL_WIDTH, LEVEL_HEIGHT};
+use rustydave::cli::{Cli, CliError, Graphics, Opt};
+use rustydave::{generate_level_with, Config, KeyColor, Tile};
 use crossterm::style::{Color, SetForegroundColor, ResetColor, Print};
 use crossterm::execute;
 
-fn main() -> std::io::Result<()> {
-    let args: Vec<String> = env::args().collect();
-    let mut level_num = None;
-    let mut use_ascii = false;
+const CLI: Cli = Cli {
+    name: "print_level",
+    about: "Prints a generated level, revealing hidden tiles.",
+    options: &[Opt::Level, Opt::Seed, Opt::Preset, Opt::Graphics, Opt::Ascii],
+    level_positional: true,
+};
 
-    for arg in args.iter().skip(1) {
-        if arg == "--ascii" {
-            use_ascii = true;
-        } else if let Ok(n) = arg.parse::<u32>() {
-            level_num = Some(n);
-        }
-    }
-
-    let level_num = match level_num {
-        Some(n) => n,
-        None => {
-            println!("Usage: {} <level_number> [--ascii]", args[0]);
-            return Ok(());
-        }
+fn main() -> std::io::Result<()> {
+    let args = CLI.parse_env();
+    let Some(level_num) = args.level else {
+        CLI.fail(&CliError("a level number is required".to_string()));
     };
+    let use_ascii = args.graphics == Graphics::Ascii;
 
-    let (level, (px, py)) = generate_level(level_num);
+    let mut config = Config::load(args.config.as_deref());
+    if let Some(preset) = args.preset {
+        config.preset = preset;
+    }
+    let seed = args.seed.unwrap_or(0);
+    let (level, (px, py)) = generate_level_with(seed, level_num, &config.gen_params());
 
     let mut out = stdout();
 
-    execute!(out, SetForegroundColor(Color::Magenta), Print(format!("--- Level {} ---\n", level_num)), ResetColor)?;
+    let note = if level_num > config.max_level { format!(" (beyond max_level {})", config.max_level) } else { String::new() };
+    execute!(out, SetForegroundColor(Color::Magenta), Print(format!("--- Level {} (seed {}){} ---\n", level_num, seed, note)), ResetColor)?;
 
-    for y in 0..LEVEL_HEIGHT {
+    for y in 0..level.height() {
         let mut row = String::new();
-        for x in 0..LEVEL_WIDTH {
+        for x in 0..level.width() {
             if x == px.floor() as usize && y == py.floor() as usize {
                 // Print buffered row so far
                 print!("{}", row);
@@ -73,6 +72,76 @@ fn main() -> std::io::Result<()> {
                         let sym = if use_ascii { "♦ " } else { "+" };
                         execute!(out, SetForegroundColor(Color::Magenta), Print(sym), ResetColor)?;
                     }
+                    Tile::Platform => {
+                        print!("{}", row);
+                        row.clear();
+                        let sym = if use_ascii { "▀▀" } else { "=" };
+                        execute!(out, SetForegroundColor(Color::Blue), Print(sym), ResetColor)?;
+                    }
+                    Tile::Key(color) => {
+                        print!("{}", row);
+                        row.clear();
+                        let sym = if use_ascii { "o¬" } else { "k" };
+                        execute!(out, SetForegroundColor(key_color(color)), Print(sym), ResetColor)?;
+                    }
+                    Tile::Crumbling | Tile::Collapsed => {
+                        print!("{}", row);
+                        row.clear();
+                        let sym = match (level[y][x], use_ascii) {
+                            (Tile::Crumbling, true) => "▒▒",
+                            (Tile::Crumbling, false) => "%",
+                            (_, true) => "..",
+                            (_, false) => ".",
+                        };
+                        execute!(out, SetForegroundColor(Color::DarkYellow), Print(sym), ResetColor)?;
+                    }
+                    Tile::FireJet { active } => {
+                        print!("{}", row);
+                        row.clear();
+                        let sym = match (active, use_ascii) {
+                            (true, true) => "╫╫",
+                            (true, false) => "!",
+                            (false, true) => "┴┴",
+                            (false, false) => "i",
+                        };
+                        execute!(out, SetForegroundColor(if active { Color::Red } else { Color::DarkGrey }), Print(sym), ResetColor)?;
+                    }
+                    Tile::Spring | Tile::ConveyorLeft | Tile::ConveyorRight | Tile::Ice => {
+                        print!("{}", row);
+                        row.clear();
+                        let (sym, color) = match level[y][x] {
+                            Tile::Spring => (if use_ascii { "◘◘" } else { "s" }, Color::Green),
+                            Tile::ConveyorLeft => (if use_ascii { "<<" } else { "<" }, Color::Grey),
+                            Tile::ConveyorRight => (if use_ascii { ">>" } else { ">" }, Color::Grey),
+                            _ => (if use_ascii { "▓▓" } else { "-" }, Color::Cyan),
+                        };
+                        execute!(out, SetForegroundColor(color), Print(sym), ResetColor)?;
+                    }
+                    Tile::Teleporter(_) => {
+                        print!("{}", row);
+                        row.clear();
+                        let sym = if use_ascii { "@@" } else { "@" };
+                        execute!(out, SetForegroundColor(Color::Magenta), Print(sym), ResetColor)?;
+                    }
+                    Tile::Warp => {
+                        // Hidden in game, but revealed here for level designers
+                        print!("{}", row);
+                        row.clear();
+                        let sym = if use_ascii { "ww" } else { "w" };
+                        execute!(out, SetForegroundColor(Color::DarkGrey), Print(sym), ResetColor)?;
+                    }
+                    Tile::Checkpoint => {
+                        print!("{}", row);
+                        row.clear();
+                        let sym = if use_ascii { "|>" } else { "f" };
+                        execute!(out, SetForegroundColor(Color::White), Print(sym), ResetColor)?;
+                    }
+                    Tile::Door(color) => {
+                        print!("{}", row);
+                        row.clear();
+                        let sym = if use_ascii { "▌▐" } else { "|" };
+                        execute!(out, SetForegroundColor(key_color(color)), Print(sym), ResetColor)?;
+                    }
                 }
             }
         }
@@ -81,3 +150,12 @@ fn main() -> std::io::Result<()> {
 
     Ok(())
 }
+
+/// Terminal color used for keys and doors of the given color.
+fn key_color(color: KeyColor) -> Color {
+    match color {
+        KeyColor::Red => Color::Red,
+        KeyColor::Green => Color::Green,
+        KeyColor::Blue => Color::Blue,
+    }
+}
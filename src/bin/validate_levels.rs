@@ -1,83 +1,131 @@
-use rustydave::{generate_level, Tile, LEVEL_WIDTH, LEVEL_HEIGHT, Config};
-use std::collections::VecDeque;
+use rustydave::cli::{Cli, Opt};
+use rustydave::{generate_level_and_archetype, is_reachable, Archetype, Tile, Config, MAX_PIT_WIDTH};
+
+const CLI: Cli = Cli {
+    name: "validate_levels",
+    about: "Checks that generated levels are well-formed and solvable.",
+    options: &[Opt::Level, Opt::Seed, Opt::Preset],
+    level_positional: false,
+};
 
 fn main() {
-    let config = Config::load();
+    let args = CLI.parse_env();
+    let mut config = Config::load(args.config.as_deref());
+    if let Some(preset) = args.preset {
+        config.preset = preset;
+    }
     let mut failures = 0;
-    let total_levels = config.max_level;
-    for seed in 1..=total_levels {
-        let (level, (px, py)) = generate_level(seed);
-        let mut seed_failed = false;
+    // --level checks just that level; otherwise every level up to max_level, and at
+    // least every step of the difficulty curve
+    let levels = match args.level {
+        Some(level) => level..=level,
+        None => 1..=config.max_level.max(config.generator.difficulty.ramp_levels),
+    };
+    let total_levels = levels.clone().count();
+    let seed = args.seed.unwrap_or(0);
+    let params = config.gen_params();
+    for level_num in levels {
+        let (level, (px, py), archetype) = generate_level_and_archetype(seed, level_num, &params);
+        let (width, height) = (level.width(), level.height());
+        let mut level_failed = false;
 
         // 1. Basic Existence Checks
         let mut trophy_pos = None;
         let mut exit_pos = None;
+        let mut keys = Vec::new();
+        let mut door_colors = Vec::new();
+        let mut teleporters: Vec<u8> = Vec::new();
 
-        for y in 0..LEVEL_HEIGHT {
-            for x in 0..LEVEL_WIDTH {
+        for y in 0..height {
+            for x in 0..width {
                 if level[y][x] == Tile::Trophy {
                     trophy_pos = Some((x, y));
-                    if y + 1 >= LEVEL_HEIGHT || level[y + 1][x] != Tile::Wall {
-                        println!("Seed {}: Trophy at ({}, {}) has no platform below!", seed, x, y);
-                        seed_failed = true;
+                    if y + 1 >= height || !level[y + 1][x].is_standable() {
+                        println!("Level {}: Trophy at ({}, {}) has no platform below!", level_num, x, y);
+                        level_failed = true;
                     }
                 }
                 if level[y][x] == Tile::Exit {
                     exit_pos = Some((x, y));
-                    if y + 1 >= LEVEL_HEIGHT || level[y + 1][x] != Tile::Wall {
-                         println!("Seed {}: Exit at ({}, {}) has no platform below!", seed, x, y);
-                         seed_failed = true;
+                    if y + 1 >= height || !level[y + 1][x].is_standable() {
+                         println!("Level {}: Exit at ({}, {}) has no platform below!", level_num, x, y);
+                         level_failed = true;
                     }
                 }
+                if let Tile::Key(color) = level[y][x] {
+                    keys.push((color, (x, y)));
+                }
+                if let Tile::Door(color) = level[y][x] && !door_colors.contains(&color) {
+                    door_colors.push(color);
+                }
+                if let Tile::Teleporter(id) = level[y][x] {
+                    teleporters.push(id);
+                }
+            }
+        }
+        keys.sort();
+
+        for &id in &teleporters {
+            if teleporters.iter().filter(|&&other| other == id).count() != 2 {
+                println!("Level {}: Teleporter {} is not part of exactly one pair!", level_num, id);
+                level_failed = true;
+                break;
+            }
+        }
+
+        for color in &door_colors {
+            if !keys.iter().any(|(k, _)| k == color) {
+                println!("Level {}: {} door has no matching key!", level_num, color.name());
+                level_failed = true;
             }
         }
 
         if trophy_pos.is_none() {
-            println!("Seed {}: No Trophy found!", seed);
-            seed_failed = true;
+            println!("Level {}: No Trophy found!", level_num);
+            level_failed = true;
         }
         if exit_pos.is_none() {
-            println!("Seed {}: No Exit found!", seed);
-            seed_failed = true;
+            println!("Level {}: No Exit found!", level_num);
+            level_failed = true;
         }
 
         // 2. Player Start Safety
         let p_tx = px.floor() as usize;
         let p_ty = py.floor() as usize;
-        if p_tx >= LEVEL_WIDTH || p_ty >= LEVEL_HEIGHT || level[p_ty][p_tx] == Tile::Wall || level[p_ty][p_tx] == Tile::Hazard {
-            println!("Seed {}: Player starts in dangerous location ({}, {})", seed, px, py);
-            seed_failed = true;
+        if p_tx >= width || p_ty >= height || level[p_ty][p_tx] == Tile::Wall || level[p_ty][p_tx].is_hazardous() {
+            println!("Level {}: Player starts in dangerous location ({}, {})", level_num, px, py);
+            level_failed = true;
         }
 
         // 3. Hazard Rule Checks
-        // Rules: 
+        // Rules (fire jets count as hazards):
         // - Single or double hazards only (max 2 consecutive)
         // - Separated by at least 3 blocks
         // - Not more than 4 hazards in any 15-block horizontal range
-        for y in 0..LEVEL_HEIGHT {
+        for y in 0..height {
             let mut x = 0;
-            while x < LEVEL_WIDTH {
-                if level[y][x] == Tile::Hazard {
+            while x < width {
+                if level[y][x].is_hazardous() {
                     let mut count = 0;
-                    while x < LEVEL_WIDTH && level[y][x] == Tile::Hazard {
+                    while x < width && level[y][x].is_hazardous() {
                         count += 1;
                         x += 1;
                     }
                     if count > 2 {
-                        println!("Seed {}: Too many consecutive hazards at y={}! Found {}", seed, y, count);
-                        seed_failed = true;
+                        println!("Level {}: Too many consecutive hazards at y={}! Found {}", level_num, y, count);
+                        level_failed = true;
                     }
                     
                     // Separation check: peek ahead for next hazard
                     let mut space = 0;
                     let sep_start = x;
-                    while x < LEVEL_WIDTH && level[y][x] != Tile::Hazard {
+                    while x < width && !level[y][x].is_hazardous() {
                         space += 1;
                         x += 1;
                     }
-                    if x < LEVEL_WIDTH && level[y][x] == Tile::Hazard && space < 3 {
-                        println!("Seed {}: Hazards too close together at y={}! Space was only {} blocks", seed, y, space);
-                        seed_failed = true;
+                    if x < width && level[y][x].is_hazardous() && space < 3 {
+                        println!("Level {}: Hazards too close together at y={}! Space was only {} blocks", level_num, y, space);
+                        level_failed = true;
                     }
                     // Backtrack to just after the hazard block to continue scanning from there
                     x = sep_start;
@@ -87,156 +135,100 @@ fn main() {
             }
             
             // Density check: sliding window of 15 tiles
-            for start_x in 0..=(LEVEL_WIDTH as i32 - 15).max(0) as usize {
+            for start_x in 0..=(width as i32 - 15).max(0) as usize {
                 let mut hazard_count = 0;
                 for i in 0..15 {
-                    if start_x + i < LEVEL_WIDTH && level[y][start_x + i] == Tile::Hazard {
+                    if start_x + i < width && level[y][start_x + i].is_hazardous() {
                         hazard_count += 1;
                     }
                 }
                 if hazard_count > 4 {
-                    println!("Seed {}: Hazard density too high at y={}, x range {}..{}", seed, y, start_x, start_x + 15);
-                    seed_failed = true;
+                    println!("Level {}: Hazard density too high at y={}, x range {}..{}", level_num, y, start_x, start_x + 15);
+                    level_failed = true;
                     break;
                 }
             }
         }
 
         // 4. Boundary Check
-        for x in 0..LEVEL_WIDTH {
+        for x in 0..width {
             if level[0][x] != Tile::Wall {
-                println!("Seed {}: Top boundary broken at x={}", seed, x);
-                seed_failed = true;
+                println!("Level {}: Top boundary broken at x={}", level_num, x);
+                level_failed = true;
+            }
+            // Pit levels are open to the bottom, but nothing else falls out
+            let bottom = level[height - 1][x];
+            let pit = archetype == Archetype::Pit && bottom == Tile::Empty;
+            if !bottom.is_standable() && !bottom.is_hazardous() && !pit {
+                println!("Level {}: Bottom boundary broken at x={}", level_num, x);
+                level_failed = true;
             }
-            if level[LEVEL_HEIGHT - 1][x] != Tile::Wall && level[LEVEL_HEIGHT - 1][x] != Tile::Hazard {
-                println!("Seed {}: Bottom boundary broken at x={}", seed, x);
-                seed_failed = true;
+        }
+        let mut pit_width = 0;
+        for x in 0..width {
+            if level[height - 1][x] == Tile::Empty {
+                pit_width += 1;
+                if pit_width == MAX_PIT_WIDTH + 1 {
+                    println!("Level {}: Pit at x={} is too wide to jump", level_num, x + 1 - pit_width);
+                    level_failed = true;
+                }
+            } else {
+                pit_width = 0;
             }
         }
-        for y in 0..LEVEL_HEIGHT {
+        for y in 0..height {
             if level[y][0] != Tile::Wall {
-                println!("Seed {}: Left boundary broken at y={}", seed, y);
-                seed_failed = true;
+                println!("Level {}: Left boundary broken at y={}", level_num, y);
+                level_failed = true;
             }
-            if level[y][LEVEL_WIDTH - 1] != Tile::Wall {
-                println!("Seed {}: Right boundary broken at y={}", seed, y);
-                seed_failed = true;
+            if level[y][width - 1] != Tile::Wall {
+                println!("Level {}: Right boundary broken at y={}", level_num, y);
+                level_failed = true;
             }
         }
 
         // 5. Reachability (BFS)
+        // Keys are collected in color order, each with only the keys before it held,
+        // then the trophy and finally the exit with every key in hand.
         if let (Some(t_pos), Some(e_pos)) = (trophy_pos, exit_pos) {
-            let start_pos = (p_tx, p_ty);
-            let can_reach_trophy = is_reachable(&level, start_pos, t_pos);
-            if !can_reach_trophy {
-                println!("Seed {}: Trophy is NOT reachable from start!", seed);
-                seed_failed = true;
-            } else {
-                let can_reach_exit = is_reachable(&level, t_pos, e_pos);
-                if !can_reach_exit {
-                    println!("Seed {}: Exit is NOT reachable from Trophy!", seed);
-                    seed_failed = true;
+            let mut from = (p_tx, p_ty);
+            let mut held = Vec::new();
+            let mut keys_ok = true;
+            for &(color, k_pos) in &keys {
+                if !is_reachable(&level, from, k_pos, &held) {
+                    println!("Level {}: {} key is NOT reachable before its door!", level_num, color.name());
+                    level_failed = true;
+                    keys_ok = false;
+                    break;
+                }
+                held.push(color);
+                from = k_pos;
+            }
+
+            if keys_ok {
+                let can_reach_trophy = is_reachable(&level, from, t_pos, &held);
+                if !can_reach_trophy {
+                    println!("Level {}: Trophy is NOT reachable from start!", level_num);
+                    level_failed = true;
+                } else {
+                    let can_reach_exit = is_reachable(&level, t_pos, e_pos, &held);
+                    if !can_reach_exit {
+                        println!("Level {}: Exit is NOT reachable from Trophy!", level_num);
+                        level_failed = true;
+                    }
                 }
             }
         }
 
-        if seed_failed {
+        if level_failed {
             failures += 1;
         }
     }
 
     if failures == 0 {
-        println!("All {total_levels} levels validated successfully!");
+        println!("All {total_levels} levels of seed {seed} validated successfully!");
     } else {
-        println!("Found {} seeds with validation failures.", failures);
+        println!("Found {} levels with validation failures.", failures);
         std::process::exit(1);
     }
 }
-
-/// A simple BFS to check reachability in the level.
-/// Accounts for horizontal movement, falling, and jumping.
-fn is_reachable(level: &[[Tile; LEVEL_WIDTH]; LEVEL_HEIGHT], start: (usize, usize), target: (usize, usize)) -> bool {
-    let mut visited = [[false; LEVEL_WIDTH]; LEVEL_HEIGHT];
-    let mut queue = VecDeque::new();
-
-    queue.push_back(start);
-    visited[start.1][start.0] = true;
-
-    while let Some((cx, cy)) = queue.pop_front() {
-        if (cx, cy) == target {
-            return true;
-        }
-
-        // Potential next positions
-        let mut neighbors = Vec::new();
-
-        let is_safe = |nx: usize, ny: usize| {
-            nx < LEVEL_WIDTH && ny < LEVEL_HEIGHT && 
-            level[ny][nx] != Tile::Wall && 
-            level[ny][nx] != Tile::Hazard
-        };
-
-        let on_ground = cy + 1 < LEVEL_HEIGHT && level[cy + 1][cx] == Tile::Wall;
-
-        // 1. Walk left/right
-        if cx > 0 && is_safe(cx - 1, cy) {
-            neighbors.push((cx - 1, cy));
-        }
-        if cx + 1 < LEVEL_WIDTH && is_safe(cx + 1, cy) {
-            neighbors.push((cx + 1, cy));
-        }
-
-        // 2. Fall down
-        if !on_ground {
-            if cy + 1 < LEVEL_HEIGHT && is_safe(cx, cy + 1) {
-                neighbors.push((cx, cy + 1));
-            }
-            // Optional: air control / diagonal falling
-            if cx > 0 && cy + 1 < LEVEL_HEIGHT && is_safe(cx - 1, cy + 1) {
-                neighbors.push((cx - 1, cy + 1));
-            }
-            if cx + 1 < LEVEL_WIDTH && cy + 1 < LEVEL_HEIGHT && is_safe(cx + 1, cy + 1) {
-                neighbors.push((cx + 1, cy + 1));
-            }
-        }
-
-        // 3. Jump (if on ground)
-        if on_ground {
-            // Dave can jump ~4 tiles high and ~20 tiles horizontally.
-            // We'll use a slightly conservative box to simulate reachable area.
-            for dy in 1..=4 {
-                if cy >= dy {
-                    let ny = cy - dy;
-                    // Horizontal range depends on height
-                    // At peak (dy=4), horizontal offset can be ~10
-                    // We'll just allow a generous range and assume Dave can make the arc.
-                    let h_range = match dy {
-                        1 => 5,
-                        2 => 8,
-                        3 => 10,
-                        4 => 12,
-                        _ => 0,
-                    };
-                    for dx in -(h_range as i32)..=(h_range as i32) {
-                        let nx = cx as i32 + dx;
-                        if nx >= 0 && nx < LEVEL_WIDTH as i32 {
-                            let nx = nx as usize;
-                            if is_safe(nx, ny) {
-                                neighbors.push((nx, ny));
-                            }
-                        }
-                    }
-                }
-            }
-        }
-
-        for (nx, ny) in neighbors {
-            if !visited[ny][nx] {
-                visited[ny][nx] = true;
-                queue.push_back((nx, ny));
-            }
-        }
-    }
-
-    false
-}
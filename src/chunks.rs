@@ -0,0 +1,290 @@
+//! Levels stitched together from hand-made rooms. The rooms are text templates in
+//! `chunks.txt`, built into the game, so new ones can be added without touching the
+//! generator: a level is a grid of rooms picked at random wherever their edges fit.
+
+use crate::{
+    is_reachable, place_checkpoint, place_keys, place_teleporters, GenParams, Landmarks, Level, SimpleRng, Tile,
+    LEVEL_HEIGHT, LEVEL_WIDTH,
+};
+use std::sync::OnceLock;
+
+/// Rooms are this many tiles square.
+const CHUNK_SIZE: usize = 10;
+
+/// The room templates built into the game.
+const TEMPLATES: &str = include_str!("../chunks.txt");
+
+/// The built-in rooms, parsed the first time a level needs them. The templates are
+/// compiled in, so a mistake in them is a bug and panics with the parse message.
+fn rooms() -> &'static [Chunk] {
+    static ROOMS: OnceLock<Vec<Chunk>> = OnceLock::new();
+    ROOMS.get_or_init(|| parse_chunks(TEMPLATES).unwrap_or_else(|e| panic!("chunks.txt: {}", e)))
+}
+
+/// How many times to lay out a fresh grid of rooms before giving up on a level.
+const LAYOUT_ATTEMPTS: usize = 40;
+
+/// A room template: `CHUNK_SIZE` rows of `CHUNK_SIZE` template characters.
+#[derive(Debug, Clone, PartialEq)]
+struct Chunk {
+    /// The name from the template's `==` line, for error messages.
+    name: String,
+    rows: Vec<Vec<char>>,
+}
+
+impl Chunk {
+    /// Whether Dave can pass through the cell at (x, y), or at least isn't walled off by it.
+    fn open(&self, x: usize, y: usize) -> bool {
+        self.rows[y][x] != '#'
+    }
+
+    /// Whether `right` can sit directly to the right of this room.
+    fn fits_left_of(&self, right: &Chunk) -> bool {
+        (0..CHUNK_SIZE).all(|y| self.open(CHUNK_SIZE - 1, y) == right.open(0, y))
+    }
+
+    /// Whether `below` can sit directly under this room.
+    fn fits_above(&self, below: &Chunk) -> bool {
+        (0..CHUNK_SIZE).all(|x| self.open(x, CHUNK_SIZE - 1) == below.open(x, 0))
+    }
+
+    /// Whether this is a starting room, with a `D` in it.
+    fn is_start(&self) -> bool {
+        self.rows.iter().any(|row| row.contains(&'D'))
+    }
+}
+
+/// Reads room templates: a `== name` line followed by `CHUNK_SIZE` rows for each
+/// room, with blank lines and `;` comments between them. Returns the first mistake
+/// found, with its line number.
+fn parse_chunks(text: &str) -> Result<Vec<Chunk>, String> {
+    let mut chunks: Vec<Chunk> = Vec::new();
+    let mut header_line = 0;
+    for (i, line) in text.lines().enumerate() {
+        let line_num = i + 1;
+        let open = chunks.last().is_some_and(|chunk| chunk.rows.len() < CHUNK_SIZE);
+        if let Some(name) = line.strip_prefix("==") {
+            if open {
+                return Err(format!("line {}: room `{}` has fewer than {} rows", header_line, chunks.last().unwrap().name, CHUNK_SIZE));
+            }
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(format!("line {}: room has no name", line_num));
+            }
+            chunks.push(Chunk { name: name.to_string(), rows: Vec::new() });
+            header_line = line_num;
+        } else if open {
+            let row: Vec<char> = line.chars().collect();
+            if row.len() != CHUNK_SIZE {
+                return Err(format!("line {}: rows must be {} tiles wide, not {}", line_num, CHUNK_SIZE, row.len()));
+            }
+            if let Some(c) = row.iter().find(|c| !"#=%-+^.DE* ".contains(**c)) {
+                return Err(format!("line {}: unknown tile `{}`", line_num, c));
+            }
+            chunks.last_mut().unwrap().rows.push(row);
+        } else if !line.trim().is_empty() && !line.starts_with(';') {
+            return Err(format!("line {}: expected a `== name` line to start a room", line_num));
+        }
+    }
+    if let Some(chunk) = chunks.last().filter(|chunk| chunk.rows.len() < CHUNK_SIZE) {
+        return Err(format!("line {}: room `{}` has fewer than {} rows", header_line, chunk.name, CHUNK_SIZE));
+    }
+
+    for chunk in &chunks {
+        let mut starts = 0;
+        for (y, row) in chunk.rows.iter().enumerate() {
+            for (x, &c) in row.iter().enumerate() {
+                if !"DE*".contains(c) {
+                    continue;
+                }
+                if x == 0 || y == 0 || x == CHUNK_SIZE - 1 || y == CHUNK_SIZE - 1 {
+                    return Err(format!("room `{}`: `{}` can't go on the edge of the room", chunk.name, c));
+                }
+                if !"#=%-".contains(chunk.rows[y + 1][x]) {
+                    return Err(format!("room `{}`: `{}` needs something to stand on below it", chunk.name, c));
+                }
+                starts += usize::from(c == 'D');
+            }
+        }
+        if starts > 1 {
+            return Err(format!("room `{}`: only one `D` per room", chunk.name));
+        }
+    }
+    Ok(chunks)
+}
+
+/// Builds a level from the built-in room templates: the starting room in the bottom
+/// left corner, the rest picked left to right and top to bottom among the rooms that
+/// fit the ones already placed. Returns `None` if no layout worked out, so the caller
+/// can fall back to another archetype.
+pub(crate) fn generate_chunks(rng: &mut SimpleRng, level_index: u32, params: &GenParams) -> Option<(Level, (f32, f32))> {
+    for _ in 0..LAYOUT_ATTEMPTS {
+        let Some(grid) = pick_rooms(rng, rooms()) else { continue };
+        if let Some(level) = build_level(rng, level_index, params, &grid) {
+            return Some(level);
+        }
+    }
+    None
+}
+
+/// Picks a room for each cell of the grid, row by row, or `None` if some cell had no
+/// room that fits its neighbours.
+fn pick_rooms<'a>(rng: &mut SimpleRng, chunks: &'a [Chunk]) -> Option<Vec<Vec<&'a Chunk>>> {
+    let (cols, rows) = (LEVEL_WIDTH / CHUNK_SIZE, LEVEL_HEIGHT / CHUNK_SIZE);
+    let mut grid: Vec<Vec<&Chunk>> = Vec::new();
+    for r in 0..rows {
+        let mut row: Vec<&Chunk> = Vec::new();
+        for c in 0..cols {
+            let start_cell = (r, c) == (rows - 1, 0);
+            let candidates: Vec<&Chunk> = chunks
+                .iter()
+                .filter(|chunk| chunk.is_start() == start_cell)
+                .filter(|chunk| row.last().is_none_or(|left| left.fits_left_of(chunk)))
+                .filter(|chunk| grid.last().is_none_or(|above: &Vec<&Chunk>| above[c].fits_above(chunk)))
+                .collect();
+            if candidates.is_empty() {
+                return None;
+            }
+            row.push(candidates[rng.range(0, candidates.len() as u32) as usize]);
+        }
+        grid.push(row);
+    }
+    Some(grid)
+}
+
+/// Lays the rooms out into a level and picks the trophy and exit among their spots,
+/// or returns `None` if there's no spot for one or the level can't be finished.
+fn build_level(rng: &mut SimpleRng, level_index: u32, params: &GenParams, grid: &[Vec<&Chunk>]) -> Option<(Level, (f32, f32))> {
+    let mut level = Level::new(LEVEL_WIDTH, LEVEL_HEIGHT);
+    let mut start = None;
+    let mut trophies = Vec::new();
+    let mut exits = Vec::new();
+    for (r, row) in grid.iter().enumerate() {
+        for (c, chunk) in row.iter().enumerate() {
+            for (cy, tiles) in chunk.rows.iter().enumerate() {
+                for (cx, &ch) in tiles.iter().enumerate() {
+                    let (x, y) = (c * CHUNK_SIZE + cx, r * CHUNK_SIZE + cy);
+                    level[y][x] = match ch {
+                        '#' => Tile::Wall,
+                        '=' => Tile::Platform,
+                        '%' => Tile::Crumbling,
+                        '-' => Tile::Ice,
+                        '+' => Tile::Diamond,
+                        '^' if rng.range(0, 100) < params.platform_hazard_chance => {
+                            if level_index >= 4 && rng.range(0, 3) == 0 { Tile::FireJet { active: false } } else { Tile::Hazard }
+                        }
+                        'D' => { start = Some((x, y)); Tile::Empty }
+                        '*' => { trophies.push((x, y)); Tile::Empty }
+                        'E' => { exits.push((x, y)); Tile::Empty }
+                        _ => Tile::Empty,
+                    };
+                }
+            }
+        }
+    }
+    // Edges facing the outside of the level are walled off
+    for x in 0..LEVEL_WIDTH {
+        level[0][x] = Tile::Wall;
+        level[LEVEL_HEIGHT - 1][x] = Tile::Wall;
+    }
+    for y in 0..LEVEL_HEIGHT {
+        level[y][0] = Tile::Wall;
+        level[y][LEVEL_WIDTH - 1] = Tile::Wall;
+    }
+    thin_hazards(&mut level);
+
+    let start = start?;
+    if trophies.is_empty() || exits.is_empty() {
+        return None;
+    }
+    let trophy = trophies[rng.range(0, trophies.len() as u32) as usize];
+    let exit = exits[rng.range(0, exits.len() as u32) as usize];
+    level[trophy.1][trophy.0] = Tile::Trophy;
+    level[exit.1][exit.0] = Tile::Exit;
+    if !is_reachable(&level, start, trophy, &[]) || !is_reachable(&level, trophy, exit, &[]) {
+        return None;
+    }
+
+    let marks = Landmarks { start, trophy, exit };
+    place_teleporters(&mut level, rng, level_index, &marks);
+    let held = place_keys(&mut level, rng, level_index, &marks);
+    // A checkpoint somewhere around the middle of the level
+    place_checkpoint(&mut level, rng, level_index, &marks, &held, |y| (5..15).contains(&y));
+
+    Some((level, (start.0 as f32 + 0.5, start.1 as f32 + 0.99)))
+}
+
+/// Takes out hazards that break the usual rules on their row, which rooms placed side
+/// by side can do between them: runs of one or two, at least three tiles apart, and
+/// no more than four in any fifteen.
+fn thin_hazards(level: &mut Level) {
+    for y in 0..level.height() {
+        let mut kept: Vec<usize> = Vec::new();
+        for x in 0..level.width() {
+            if !level[y][x].is_hazardous() {
+                continue;
+            }
+            let ok = match kept.last() {
+                Some(&last) if last + 1 == x => kept.len() < 2 || kept[kept.len() - 2] + 2 != x,
+                Some(&last) => x - last > 3,
+                None => true,
+            } && kept.iter().filter(|&&k| k + 14 >= x).count() < 4;
+            if ok {
+                kept.push(x);
+            } else {
+                level[y][x] = Tile::Empty;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_built_in_rooms_parse() {
+        let chunks = rooms();
+        assert!(chunks.iter().any(Chunk::is_start));
+        assert!(chunks.iter().any(|chunk| chunk.rows.iter().any(|row| row.contains(&'*'))));
+        assert!(chunks.iter().any(|chunk| chunk.rows.iter().any(|row| row.contains(&'E'))));
+    }
+
+    #[test]
+    fn test_room_mistakes_are_reported() {
+        let room = |rows: &[&str]| format!("; a room\n== test\n{}\n", rows.join("\n"));
+        let mut rows = ["##########"; CHUNK_SIZE];
+        rows[4] = "#........#";
+        rows[5] = "#.D......#";
+        assert!(parse_chunks(&room(&rows)).is_ok());
+
+        rows[6] = "#........#";
+        assert!(parse_chunks(&room(&rows)).unwrap_err().contains("`D` needs something to stand on"));
+        rows[6] = "#..?.....#";
+        assert_eq!(parse_chunks(&room(&rows)), Err("line 9: unknown tile `?`".to_string()));
+        rows[6] = "##########";
+        assert_eq!(parse_chunks(&room(&rows[..9])), Err("line 2: room `test` has fewer than 10 rows".to_string()));
+        assert_eq!(parse_chunks("##########\n"), Err("line 1: expected a `== name` line to start a room".to_string()));
+    }
+
+    #[test]
+    fn test_rooms_fit_along_matching_edges() {
+        let chunks = rooms();
+        let room = |name: &str| chunks.iter().find(|chunk| chunk.name == name).unwrap();
+        assert!(room("start").fits_left_of(room("hall")));
+        assert!(!room("hall").fits_left_of(room("cavern")));
+        assert!(room("loft").fits_above(room("climb")));
+        assert!(!room("loft").fits_above(room("hall")));
+    }
+
+    #[test]
+    fn test_hazards_thinned_to_the_row_rules() {
+        let mut level = Level::new(LEVEL_WIDTH, 3);
+        for x in [3, 4, 5, 10, 12, 20, 21, 25, 26, 30] {
+            level[1][x] = Tile::Hazard;
+        }
+        thin_hazards(&mut level);
+        let kept: Vec<usize> = (0..LEVEL_WIDTH).filter(|&x| level[1][x] == Tile::Hazard).collect();
+        assert_eq!(kept, vec![3, 4, 10, 20, 21, 25, 26]);
+    }
+}
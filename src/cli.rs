@@ -0,0 +1,358 @@
+//! Command-line parsing shared by the game and the level tools, so every binary
+//! spells its options the same way and reports mistakes the same way.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::Preset;
+
+/// Version reported by `--version`.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Most lives `--lives` accepts.
+pub const MAX_LIVES: u32 = 99;
+
+/// How tiles are drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Graphics {
+    /// Single-character tiles.
+    #[default]
+    Classic,
+    /// Two-character wide tiles.
+    Ascii,
+}
+
+impl Graphics {
+    const NAMES: [(&'static str, Graphics); 2] = [("classic", Graphics::Classic), ("ascii", Graphics::Ascii)];
+
+    /// Parses a mode name as given to `--graphics`, ignoring case.
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::NAMES.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|&(_, mode)| mode)
+    }
+}
+
+/// An option a binary can accept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opt {
+    Help,
+    Version,
+    Seed,
+    Level,
+    Lives,
+    Preset,
+    Config,
+    Graphics,
+    /// Shorthand for `--graphics ascii`.
+    Ascii,
+    CheckConfig,
+}
+
+impl Opt {
+    const ALL: [Opt; 10] = [
+        Opt::Help,
+        Opt::Version,
+        Opt::Seed,
+        Opt::Level,
+        Opt::Lives,
+        Opt::Preset,
+        Opt::Config,
+        Opt::Graphics,
+        Opt::Ascii,
+        Opt::CheckConfig,
+    ];
+
+    /// The long name, including the leading dashes.
+    pub fn name(self) -> &'static str {
+        match self {
+            Opt::Help => "--help",
+            Opt::Version => "--version",
+            Opt::Seed => "--seed",
+            Opt::Level => "--level",
+            Opt::Lives => "--lives",
+            Opt::Preset => "--preset",
+            Opt::Config => "--config",
+            Opt::Graphics => "--graphics",
+            Opt::Ascii => "--ascii",
+            Opt::CheckConfig => "--check-config",
+        }
+    }
+
+    /// The single-letter alias, if there is one.
+    fn short(self) -> Option<&'static str> {
+        match self {
+            Opt::Help => Some("-h"),
+            Opt::Version => Some("-V"),
+            _ => None,
+        }
+    }
+
+    /// Placeholder for the option's value in help text, `None` for flags.
+    fn value_name(self) -> Option<&'static str> {
+        match self {
+            Opt::Seed => Some("<n>"),
+            Opt::Level => Some("<n>"),
+            Opt::Lives => Some("<n>"),
+            Opt::Preset => Some("<name>"),
+            Opt::Config => Some("<path>"),
+            Opt::Graphics => Some("<mode>"),
+            _ => None,
+        }
+    }
+
+    fn help(self) -> &'static str {
+        match self {
+            Opt::Help => "Print this help and exit",
+            Opt::Version => "Print the version and exit",
+            Opt::Seed => "Master seed the levels are generated from",
+            Opt::Level => "Level number to use",
+            Opt::Lives => "Number of lives to start with",
+            Opt::Preset => "Difficulty preset: easy, normal or hard",
+            Opt::Config => "Read this config file on top of the usual ones",
+            Opt::Graphics => "Tile style: classic or ascii",
+            Opt::Ascii => "Same as --graphics ascii",
+            Opt::CheckConfig => "Check the config files and exit",
+        }
+    }
+
+    fn matches(self, arg: &str) -> bool {
+        arg == self.name() || self.short() == Some(arg)
+    }
+}
+
+/// Options understood by every binary.
+pub const COMMON: [Opt; 3] = [Opt::Help, Opt::Version, Opt::Config];
+
+/// The settings given on the command line. Anything not given is left at its default.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Args {
+    pub seed: Option<u32>,
+    pub level: Option<u32>,
+    pub lives: Option<u32>,
+    pub preset: Option<Preset>,
+    pub config: Option<PathBuf>,
+    pub graphics: Graphics,
+    pub check_config: bool,
+}
+
+/// What the command line asks for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Run(Args),
+    Help,
+    Version,
+}
+
+/// A command line that couldn't be understood.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CliError(pub String);
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Describes the command line of one binary.
+pub struct Cli {
+    /// Program name used in usage and version output.
+    pub name: &'static str,
+    /// One-line description shown at the top of `--help`.
+    pub about: &'static str,
+    /// Options accepted besides `COMMON`.
+    pub options: &'static [Opt],
+    /// Whether a bare number is taken as the level, as in `rustydave 3`.
+    pub level_positional: bool,
+}
+
+impl Cli {
+    /// Parses the arguments after the program name.
+    pub fn parse(&self, args: &[String]) -> Result<Command, CliError> {
+        let mut parsed = Args::default();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if !arg.starts_with('-') || arg.len() == 1 {
+                match arg.parse::<u32>() {
+                    Ok(level) if self.level_positional && parsed.level.is_none() => parsed.level = Some(at_least_one("level", level)?),
+                    _ => return Err(CliError(format!("unexpected argument '{}'", arg))),
+                }
+                continue;
+            }
+
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            let opt = Opt::ALL
+                .into_iter()
+                .find(|opt| opt.matches(flag) && (COMMON.contains(opt) || self.options.contains(opt)))
+                .ok_or_else(|| CliError(format!("unknown option '{}'", flag)))?;
+
+            let value = match (opt.value_name(), inline) {
+                (Some(_), Some(value)) => value,
+                (Some(_), None) => iter
+                    .next()
+                    .cloned()
+                    .ok_or_else(|| CliError(format!("{} needs a value", opt.name())))?,
+                (None, Some(_)) => return Err(CliError(format!("{} doesn't take a value", opt.name()))),
+                (None, None) => String::new(),
+            };
+
+            match opt {
+                Opt::Help => return Ok(Command::Help),
+                Opt::Version => return Ok(Command::Version),
+                Opt::Seed => parsed.seed = Some(number(opt, &value)?),
+                Opt::Level => parsed.level = Some(at_least_one("level", number(opt, &value)?)?),
+                Opt::Lives => parsed.lives = Some(at_most("lives", MAX_LIVES, at_least_one("lives", number(opt, &value)?)?)?),
+                Opt::Preset => {
+                    parsed.preset = Some(
+                        Preset::parse(&value)
+                            .ok_or_else(|| CliError(format!("unknown preset '{}' (expected easy, normal or hard)", value)))?,
+                    );
+                }
+                Opt::Config => parsed.config = Some(PathBuf::from(value)),
+                Opt::Graphics => {
+                    parsed.graphics = Graphics::parse(&value)
+                        .ok_or_else(|| CliError(format!("unknown graphics mode '{}' (expected classic or ascii)", value)))?;
+                }
+                Opt::Ascii => parsed.graphics = Graphics::Ascii,
+                Opt::CheckConfig => parsed.check_config = true,
+            }
+        }
+        Ok(Command::Run(parsed))
+    }
+
+    /// Parses the process's own arguments. Prints help or the version and exits if
+    /// asked to, and exits with status 2 on a bad command line.
+    pub fn parse_env(&self) -> Args {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        match self.parse(&args) {
+            Ok(Command::Run(args)) => args,
+            Ok(Command::Help) => {
+                print!("{}", self.help());
+                std::process::exit(0);
+            }
+            Ok(Command::Version) => {
+                println!("{} {}", self.name, VERSION);
+                std::process::exit(0);
+            }
+            Err(e) => self.fail(&e),
+        }
+    }
+
+    /// Reports a command-line error and exits with status 2.
+    pub fn fail(&self, error: &CliError) -> ! {
+        eprintln!("{}: {}", self.name, error);
+        eprintln!("Try '{} --help' for more information.", self.name);
+        std::process::exit(2);
+    }
+
+    /// The text printed by `--help`.
+    pub fn help(&self) -> String {
+        let positional = if self.level_positional { " [LEVEL]" } else { "" };
+        let mut text = format!("{}\n\nUsage: {} [OPTIONS]{}\n\nOptions:\n", self.about, self.name, positional);
+        let opts: Vec<Opt> = Opt::ALL.into_iter().filter(|opt| COMMON.contains(opt) || self.options.contains(opt)).collect();
+        let spelled: Vec<String> = opts
+            .iter()
+            .map(|opt| {
+                let short = opt.short().map_or_else(|| "    ".to_string(), |s| format!("{}, ", s));
+                match opt.value_name() {
+                    Some(value) => format!("{}{} {}", short, opt.name(), value),
+                    None => format!("{}{}", short, opt.name()),
+                }
+            })
+            .collect();
+        let width = spelled.iter().map(|s| s.len()).max().unwrap_or(0);
+        for (opt, spelled) in opts.iter().zip(&spelled) {
+            text.push_str(&format!("  {:width$}  {}\n", spelled, opt.help(), width = width));
+        }
+        text
+    }
+}
+
+fn number(opt: Opt, value: &str) -> Result<u32, CliError> {
+    value.parse().map_err(|_| CliError(format!("{} expects a whole number, got '{}'", opt.name(), value)))
+}
+
+fn at_least_one(what: &str, n: u32) -> Result<u32, CliError> {
+    if n == 0 {
+        return Err(CliError(format!("{} must be at least 1", what)));
+    }
+    Ok(n)
+}
+
+fn at_most(what: &str, max: u32, n: u32) -> Result<u32, CliError> {
+    if n > max {
+        return Err(CliError(format!("{} must be at most {}", what, max)));
+    }
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAME: Cli = Cli {
+        name: "rustydave",
+        about: "",
+        options: &[Opt::Seed, Opt::Level, Opt::Lives, Opt::Preset, Opt::Graphics, Opt::Ascii, Opt::CheckConfig],
+        level_positional: true,
+    };
+
+    fn parse(cli: &Cli, args: &[&str]) -> Result<Command, CliError> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        cli.parse(&args)
+    }
+
+    fn run(cli: &Cli, args: &[&str]) -> Args {
+        match parse(cli, args) {
+            Ok(Command::Run(args)) => args,
+            other => panic!("expected arguments, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_options() {
+        assert_eq!(run(&GAME, &[]), Args::default());
+        let args = run(&GAME, &["3", "--ascii", "--seed", "42", "--lives=5", "--preset", "Hard", "--config", "x.toml"]);
+        assert_eq!(args.level, Some(3));
+        assert_eq!(args.graphics, Graphics::Ascii);
+        assert_eq!(args.seed, Some(42));
+        assert_eq!(args.lives, Some(5));
+        assert_eq!(args.preset, Some(Preset::Hard));
+        assert_eq!(args.config, Some(PathBuf::from("x.toml")));
+        assert_eq!(run(&GAME, &["--level", "7", "--graphics", "ASCII"]).level, Some(7));
+        assert_eq!(run(&GAME, &["--config=y.toml"]).config, Some(PathBuf::from("y.toml")));
+        assert!(run(&GAME, &["--check-config"]).check_config);
+
+        assert_eq!(parse(&GAME, &["3", "--help"]), Ok(Command::Help));
+        assert_eq!(parse(&GAME, &["-V"]), Ok(Command::Version));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let tool = Cli { name: "validate_levels", about: "", options: &[Opt::Seed], level_positional: false };
+        let error = |cli: &Cli, args: &[&str]| parse(cli, args).unwrap_err().0;
+        assert_eq!(error(&GAME, &["--fast"]), "unknown option '--fast'");
+        assert_eq!(error(&GAME, &["--seed"]), "--seed needs a value");
+        assert_eq!(error(&GAME, &["--seed", "abc"]), "--seed expects a whole number, got 'abc'");
+        assert_eq!(error(&GAME, &["--ascii=yes"]), "--ascii doesn't take a value");
+        assert_eq!(error(&GAME, &["--lives", "0"]), "lives must be at least 1");
+        assert_eq!(error(&GAME, &["--lives", "3000000000"]), "lives must be at most 99");
+        assert_eq!(error(&GAME, &["0"]), "level must be at least 1");
+        assert_eq!(error(&GAME, &["3", "4"]), "unexpected argument '4'");
+        assert!(error(&GAME, &["--preset", "brutal"]).starts_with("unknown preset 'brutal'"));
+        assert!(error(&GAME, &["--graphics", "fancy"]).starts_with("unknown graphics mode 'fancy'"));
+        // Options another binary accepts are still unknown here
+        assert_eq!(error(&tool, &["--lives", "2"]), "unknown option '--lives'");
+        assert_eq!(error(&tool, &["3"]), "unexpected argument '3'");
+    }
+
+    #[test]
+    fn test_help_lists_accepted_options() {
+        let help = GAME.help();
+        assert!(help.contains("Usage: rustydave [OPTIONS] [LEVEL]"));
+        assert!(help.contains("-h, --help"));
+        assert!(help.contains("--graphics <mode>"));
+        let tool = Cli { name: "validate_levels", about: "", options: &[], level_positional: false };
+        assert!(!tool.help().contains("--lives"));
+    }
+}
@@ -0,0 +1,356 @@
+//! Keyboard input: the actions Dave can perform and the keys bound to them.
+//! Bindings are parsed once from `KeysConfig` into an `ActionMap`, so handling a key
+//! press is a single lookup.
+
+use std::collections::{HashMap, HashSet};
+use crossterm::event::{KeyCode, KeyEvent, KeyEventState, KeyModifiers};
+use crate::{InputConfig, KeysConfig, ReleaseMode};
+
+/// Something the player can do, bound to keys in the `[keys]` section.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Action {
+    Left,
+    Right,
+    /// Jump; also moves up in menus.
+    Jump,
+    /// Drop through platforms (with Jump); also moves down in menus.
+    Down,
+    Pause,
+    /// Opens the pause menu while playing; goes back in menus.
+    Quit,
+    /// Respawns, continues to the next level and confirms menu entries.
+    Restart,
+}
+
+impl Action {
+    pub const ALL: [Action; 7] = [
+        Action::Left,
+        Action::Right,
+        Action::Jump,
+        Action::Down,
+        Action::Pause,
+        Action::Quit,
+        Action::Restart,
+    ];
+
+    /// The action's name in `[keys]`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::Jump => "jump",
+            Action::Down => "down",
+            Action::Pause => "pause",
+            Action::Quit => "quit",
+            Action::Restart => "restart",
+        }
+    }
+}
+
+/// Keys with a name of more than one character, other than function and number pad keys.
+const NAMED_KEYS: [(&str, KeyCode); 16] = [
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Enter", KeyCode::Enter),
+    ("Esc", KeyCode::Esc),
+    ("Space", KeyCode::Char(' ')),
+    ("Tab", KeyCode::Tab),
+    ("BackTab", KeyCode::BackTab),
+    ("Backspace", KeyCode::Backspace),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Insert", KeyCode::Insert),
+    ("Delete", KeyCode::Delete),
+];
+
+/// Keys on the number pad besides the digits, named `Num` followed by the character.
+const KEYPAD_SYMBOLS: [char; 5] = ['+', '-', '*', '/', '.'];
+
+/// A key as bound in the config: the key code, its Ctrl/Alt/Shift modifiers and
+/// whether it's on the number pad.
+///
+/// Letters are stored in lowercase and Shift is dropped for characters, so bindings
+/// don't depend on Shift or Caps Lock. Shift+Tab is stored as `BackTab`, which is what
+/// terminals send.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct KeyBinding {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+    /// Number pad key. Only terminals with the kitty keyboard protocol report these;
+    /// elsewhere number pad keys arrive as their ordinary counterparts.
+    pub keypad: bool,
+}
+
+impl KeyBinding {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        Self::with_keypad(code, modifiers, false)
+    }
+
+    fn with_keypad(code: KeyCode, modifiers: KeyModifiers, keypad: bool) -> Self {
+        let mut modifiers = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        let code = match code {
+            KeyCode::Char(c) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::Char(c.to_lowercase().next().unwrap_or(c))
+            }
+            KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+            other => other,
+        };
+        if code == KeyCode::BackTab {
+            modifiers.insert(KeyModifiers::SHIFT);
+        }
+        KeyBinding { code, modifiers, keypad }
+    }
+
+    /// The binding a terminal key event corresponds to.
+    pub fn from_event(event: &KeyEvent) -> Self {
+        Self::with_keypad(event.code, event.modifiers, event.state.contains(KeyEventState::KEYPAD))
+    }
+
+    /// Parses a key name from the config, or returns `None` if it isn't one.
+    ///
+    /// A name is a key (`a`, `Space`, `F5`, `PageUp`, `Num8`, `NumEnter`, ...)
+    /// optionally preceded by modifiers joined with `+`, as in `Ctrl+R` or `Shift+Tab`.
+    /// Apart from single characters and the `Num` prefix, names ignore case.
+    pub fn parse(name: &str) -> Option<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = name;
+        // A trailing '+' is the key itself, as in "Num+" or "Ctrl++"
+        while let Some((prefix, tail)) = rest.split_once('+').filter(|(_, tail)| !tail.is_empty()) {
+            modifiers |= match prefix.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return None,
+            };
+            rest = tail;
+        }
+
+        let (code, keypad) = if let Some(&(_, code)) = NAMED_KEYS.iter().find(|(n, _)| n.eq_ignore_ascii_case(rest)) {
+            (code, false)
+        } else if let Some(n) = rest.strip_prefix(['F', 'f']).and_then(|n| n.parse::<u8>().ok()) {
+            if !(1..=24).contains(&n) {
+                return None;
+            }
+            (KeyCode::F(n), false)
+        } else if let Some(key) = rest.strip_prefix("Num").filter(|k| !k.is_empty()) {
+            match key {
+                "Enter" => (KeyCode::Enter, true),
+                _ => {
+                    let mut chars = key.chars();
+                    let c = chars.next()?;
+                    if chars.next().is_some() || !(c.is_ascii_digit() || KEYPAD_SYMBOLS.contains(&c)) {
+                        return None;
+                    }
+                    (KeyCode::Char(c), true)
+                }
+            }
+        } else {
+            let mut chars = rest.chars();
+            let c = chars.next()?;
+            if chars.next().is_some() {
+                return None;
+            }
+            (KeyCode::Char(c), false)
+        };
+        Some(Self::with_keypad(code, modifiers, keypad))
+    }
+
+    /// The config name for this key (the inverse of `parse`), or `None` if it can't be
+    /// written in `config.toml`.
+    pub fn name(&self) -> Option<String> {
+        let key = match self.code {
+            KeyCode::Enter if self.keypad => "NumEnter".to_string(),
+            KeyCode::Char(c) if self.keypad => format!("Num{}", c),
+            KeyCode::F(n) => format!("F{}", n),
+            // Written as Shift+Tab; the Shift comes from the modifiers
+            KeyCode::BackTab => "Tab".to_string(),
+            KeyCode::Char(c) if c.is_ascii_graphic() => c.to_string(),
+            code => NAMED_KEYS.iter().find(|(_, k)| *k == code)?.0.to_string(),
+        };
+
+        let mut name = String::new();
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            name.push_str("Ctrl+");
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            name.push_str("Alt+");
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            name.push_str("Shift+");
+        }
+        name.push_str(&key);
+        Some(name)
+    }
+}
+
+impl From<KeyCode> for KeyBinding {
+    fn from(code: KeyCode) -> Self {
+        KeyBinding::new(code, KeyModifiers::NONE)
+    }
+}
+
+/// Which actions each key triggers.
+#[derive(Debug, Clone, Default)]
+pub struct ActionMap {
+    bindings: HashMap<KeyBinding, Vec<Action>>,
+}
+
+impl ActionMap {
+    /// Builds the map from the config bindings. Names that don't parse are skipped;
+    /// config loading reports and removes them.
+    pub fn new(keys: &KeysConfig) -> Self {
+        let mut bindings: HashMap<KeyBinding, Vec<Action>> = HashMap::new();
+        for action in Action::ALL {
+            for key in keys.binding(action).iter().filter_map(|name| KeyBinding::parse(name)) {
+                let actions = bindings.entry(key).or_default();
+                if !actions.contains(&action) {
+                    actions.push(action);
+                }
+            }
+        }
+        ActionMap { bindings }
+    }
+
+    /// The actions bound to `key`. A number pad key with no binding of its own falls
+    /// back to the bindings of its ordinary counterpart, and a chord with no binding of
+    /// its own to those of the key without modifiers, so holding Shift or Ctrl doesn't
+    /// stop Dave.
+    pub fn actions(&self, key: KeyBinding) -> &[Action] {
+        match self.bindings.get(&key) {
+            Some(actions) => actions,
+            None if key.keypad => self.actions(KeyBinding { keypad: false, ..key }),
+            None if !key.modifiers.is_empty() => self.actions(KeyBinding { modifiers: KeyModifiers::NONE, ..key }),
+            None => &[],
+        }
+    }
+
+    /// Keys bound to more than one action, with those actions, sorted by key name.
+    pub fn conflicts(&self) -> Vec<(String, Vec<Action>)> {
+        let mut conflicts: Vec<(String, Vec<Action>)> = self
+            .bindings
+            .iter()
+            .filter(|(_, actions)| actions.len() > 1)
+            .map(|(key, actions)| (key.name().unwrap_or_else(|| format!("{:?}", key.code)), actions.clone()))
+            .collect();
+        conflicts.sort();
+        conflicts
+    }
+
+    /// Every action bound to at least one of the held keys.
+    pub fn held<'a>(&self, keys: impl IntoIterator<Item = &'a KeyBinding>) -> HashSet<Action> {
+        keys.into_iter().flat_map(|&key| self.actions(key).iter().copied()).collect()
+    }
+}
+
+/// The keys currently held down.
+///
+/// Terminals that report key releases make this exact. Others only send a press,
+/// then auto-repeats while the key stays down, so each key instead counts as held for
+/// a while after its last press or repeat (see `InputConfig`).
+#[derive(Debug, Clone)]
+pub struct HeldKeys {
+    /// Each held key with the time left before it counts as released, or `None` if it
+    /// stays held until its release event.
+    keys: HashMap<KeyBinding, Option<f32>>,
+    /// Whether release events are relied on.
+    releases: bool,
+    /// Whether the terminal reports releases, as far as we know: said up front, or
+    /// a release event has been seen since.
+    reported: bool,
+    mode: ReleaseMode,
+    press_hold: f32,
+    repeat_hold: f32,
+}
+
+impl HeldKeys {
+    /// `releases_reported` is whether the terminal is known to report key releases.
+    pub fn new(config: &InputConfig, releases_reported: bool) -> Self {
+        HeldKeys {
+            keys: HashMap::new(),
+            releases: uses_releases(config.release_mode, releases_reported),
+            reported: releases_reported,
+            mode: config.release_mode,
+            press_hold: config.press_hold,
+            repeat_hold: config.repeat_hold,
+        }
+    }
+
+    /// Picks up a new release mode and hold times after the config changes. Held keys
+    /// are forgotten if that switches between release events and repeat timing.
+    pub fn configure(&mut self, config: &InputConfig) {
+        let releases = uses_releases(config.release_mode, self.reported);
+        if releases != self.releases {
+            self.keys.clear();
+        }
+        self.releases = releases;
+        self.mode = config.release_mode;
+        self.press_hold = config.press_hold;
+        self.repeat_hold = config.repeat_hold;
+    }
+
+    /// Whether releases come from release events rather than repeat timing.
+    pub fn uses_releases(&self) -> bool {
+        self.releases
+    }
+
+    /// Records a press or auto-repeat of `key`.
+    pub fn press(&mut self, key: KeyBinding) {
+        let hold = if self.releases {
+            None
+        } else if self.keys.contains_key(&key) {
+            Some(self.repeat_hold)
+        } else {
+            Some(self.press_hold)
+        };
+        self.keys.insert(key, hold);
+    }
+
+    /// Records a release of `key`. In auto mode, the first release event switches
+    /// over to relying on them.
+    pub fn release(&mut self, key: KeyBinding) {
+        self.reported = true;
+        if !self.releases && self.mode == ReleaseMode::Auto {
+            self.releases = true;
+            for hold in self.keys.values_mut() {
+                *hold = None;
+            }
+        }
+        // Modifiers may have been let go first, so match on the key alone
+        self.keys.retain(|held, _| held.code != key.code);
+    }
+
+    /// Counts down hold times, dropping keys whose time has run out.
+    pub fn tick(&mut self, dt: f32) {
+        self.keys.retain(|_, hold| match hold {
+            Some(time) => {
+                *time -= dt;
+                *time > 0.0
+            }
+            None => true,
+        });
+    }
+
+    /// Forgets every held key.
+    pub fn clear(&mut self) {
+        self.keys.clear();
+    }
+
+    /// The keys currently held, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = &KeyBinding> {
+        self.keys.keys()
+    }
+}
+
+/// Whether `mode` relies on release events, given whether the terminal reports them.
+fn uses_releases(mode: ReleaseMode, reported: bool) -> bool {
+    match mode {
+        ReleaseMode::Auto => reported,
+        ReleaseMode::Release => true,
+        ReleaseMode::Repeat => false,
+    }
+}
@@ -1,17 +1,36 @@
 //! Shared library for Rusty Dave game logic.
 //! Contains level generation, tile definitions, and random number generation.
 
+pub mod cli;
+pub mod input;
+mod chunks;
+
+pub use input::{Action, ActionMap, HeldKeys, KeyBinding};
+
+use std::collections::VecDeque;
+use std::ops::{Index, IndexMut};
+use std::fmt;
+use std::env;
 use std::fs;
 use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 use serde::{Deserialize, Serialize};
-use crossterm::event::KeyCode;
+use toml_edit::DocumentMut;
 
 /// Width of the game level in tiles.
 pub const LEVEL_WIDTH: usize = 60;
-/// Height of the game level in tiles.
+/// Height of the game level in tiles; also how many rows of a taller level fit on screen.
 pub const LEVEL_HEIGHT: usize = 20;
+/// Widest pit the generator cuts through the floor; any wider and Dave couldn't jump it.
+pub const MAX_PIT_WIDTH: usize = 4;
+/// Name of the config file, both in the working directory and in config directories.
+pub const CONFIG_PATH: &str = "config.toml";
+/// Environment variable naming a config file that overrides the others.
+pub const CONFIG_ENV: &str = "RUSTYDAVE_CONFIG";
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct PhysicsConfig {
     /// Target horizontal velocity in units/second.
     pub target_vx: f32,
@@ -34,6 +53,7 @@ pub struct PhysicsConfig {
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct KeysConfig {
     /// Keys mapped to moving left.
     pub left: Vec<String>,
@@ -41,21 +61,345 @@ pub struct KeysConfig {
     pub right: Vec<String>,
     /// Keys mapped to jumping.
     pub jump: Vec<String>,
-    /// Keys mapped to quitting the game.
+    /// Keys mapped to moving down; held together with jump to drop through platforms.
+    pub down: Vec<String>,
+    /// Keys mapped to opening the pause menu while playing.
+    pub pause: Vec<String>,
+    /// Keys mapped to quitting the game; during play they open the pause menu.
     pub quit: Vec<String>,
-    /// Keys mapped to restarting or continuing to the next level.
+    /// Keys mapped to restarting, continuing to the next level and confirming menu entries.
     pub restart: Vec<String>,
 }
 
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        PhysicsConfig {
+            target_vx: 30.0,
+            accel_ground: 200.0,
+            accel_air: 80.0,
+            jump_vy: -28.0,
+            gravity: 80.0,
+            coyote_time: 0.1,
+            jump_buffer_time: 0.1,
+            jump_release_gravity_mult: 3.0,
+            friction: 400.0,
+        }
+    }
+}
+
+impl PhysicsConfig {
+    /// Every physics value with its name in `[physics]`.
+    pub fn fields(&self) -> [(&'static str, f32); 9] {
+        [
+            ("target_vx", self.target_vx),
+            ("accel_ground", self.accel_ground),
+            ("accel_air", self.accel_air),
+            ("jump_vy", self.jump_vy),
+            ("gravity", self.gravity),
+            ("coyote_time", self.coyote_time),
+            ("jump_buffer_time", self.jump_buffer_time),
+            ("jump_release_gravity_mult", self.jump_release_gravity_mult),
+            ("friction", self.friction),
+        ]
+    }
+
+    /// Checks every value against its allowed range, returning the name and requirement
+    /// of each one that is out of range.
+    pub fn out_of_range(&self) -> Vec<(&'static str, &'static str)> {
+        self.fields()
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let (ok, requirement) = match name {
+                    "jump_vy" => (value < 0.0, "must be negative (up is negative)"),
+                    "coyote_time" | "jump_buffer_time" => ((0.0..=1.0).contains(&value), "must be between 0 and 1 second"),
+                    "jump_release_gravity_mult" => (value >= 1.0, "must be at least 1"),
+                    "friction" => (value >= 0.0, "must not be negative"),
+                    _ => (value > 0.0, "must be positive"),
+                };
+                (!ok || !value.is_finite()).then_some((name, requirement))
+            })
+            .collect()
+    }
+
+    /// Mutable access to a physics value by its name in `[physics]`.
+    pub fn field_mut(&mut self, name: &str) -> Option<&mut f32> {
+        match name {
+            "target_vx" => Some(&mut self.target_vx),
+            "accel_ground" => Some(&mut self.accel_ground),
+            "accel_air" => Some(&mut self.accel_air),
+            "jump_vy" => Some(&mut self.jump_vy),
+            "gravity" => Some(&mut self.gravity),
+            "coyote_time" => Some(&mut self.coyote_time),
+            "jump_buffer_time" => Some(&mut self.jump_buffer_time),
+            "jump_release_gravity_mult" => Some(&mut self.jump_release_gravity_mult),
+            "friction" => Some(&mut self.friction),
+            _ => None,
+        }
+    }
+}
+
+impl Default for KeysConfig {
+    fn default() -> Self {
+        KeysConfig {
+            left: vec!["Left".to_string(), "a".to_string(), "A".to_string()],
+            right: vec!["Right".to_string(), "d".to_string(), "D".to_string()],
+            jump: vec!["Up".to_string(), "w".to_string(), "W".to_string(), "Space".to_string()],
+            down: vec!["Down".to_string(), "s".to_string(), "S".to_string()],
+            pause: vec!["p".to_string(), "P".to_string()],
+            quit: vec!["Esc".to_string(), "q".to_string(), "Q".to_string()],
+            restart: vec!["Enter".to_string()],
+        }
+    }
+}
+
+impl KeysConfig {
+    /// The key names bound to `action`.
+    pub fn binding(&self, action: Action) -> &Vec<String> {
+        match action {
+            Action::Left => &self.left,
+            Action::Right => &self.right,
+            Action::Jump => &self.jump,
+            Action::Down => &self.down,
+            Action::Pause => &self.pause,
+            Action::Quit => &self.quit,
+            Action::Restart => &self.restart,
+        }
+    }
+
+    /// Mutable access to the key names bound to `action`.
+    pub fn binding_mut(&mut self, action: Action) -> &mut Vec<String> {
+        match action {
+            Action::Left => &mut self.left,
+            Action::Right => &mut self.right,
+            Action::Jump => &mut self.jump,
+            Action::Down => &mut self.down,
+            Action::Pause => &mut self.pause,
+            Action::Quit => &mut self.quit,
+            Action::Restart => &mut self.restart,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SpringConfig {
+    /// Upward velocity a spring launches Dave with (negative value).
+    pub vy: f32,
+}
+
+impl Default for SpringConfig {
+    fn default() -> Self {
+        SpringConfig { vy: -40.0 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConveyorConfig {
+    /// Horizontal velocity a conveyor belt adds while Dave stands on it.
+    pub vx: f32,
+}
+
+impl Default for ConveyorConfig {
+    fn default() -> Self {
+        ConveyorConfig { vx: 12.0 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IceConfig {
+    /// Multiplier applied to `physics.friction` while standing on ice.
+    pub friction_mult: f32,
+    /// Multiplier applied to `physics.accel_ground` while standing on ice.
+    pub accel_mult: f32,
+}
+
+impl Default for IceConfig {
+    fn default() -> Self {
+        IceConfig { friction_mult: 0.1, accel_mult: 0.3 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TeleporterConfig {
+    /// Whether Dave keeps his velocity when teleporting (otherwise he arrives standing still).
+    pub preserve_velocity: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WarpConfig {
+    /// Number of levels a warp zone skips ahead.
+    pub skip_levels: u32,
+}
+
+impl Default for WarpConfig {
+    fn default() -> Self {
+        WarpConfig { skip_levels: 3 }
+    }
+}
+
+/// How the game finds out that a key has been let go.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ReleaseMode {
+    /// Use release events if the terminal reports them, otherwise repeat timing.
+    #[default]
+    Auto,
+    /// Always rely on release events.
+    Release,
+    /// Always infer releases from repeat timing.
+    Repeat,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct InputConfig {
+    /// How key releases are detected.
+    pub release_mode: ReleaseMode,
+    /// Without release events: seconds a key counts as held after it is first pressed.
+    /// Must cover the terminal's delay before auto-repeat starts.
+    pub press_hold: f32,
+    /// Without release events: seconds a key counts as held after each auto-repeat.
+    pub repeat_hold: f32,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        InputConfig { release_mode: ReleaseMode::Auto, press_hold: 0.55, repeat_hold: 0.1 }
+    }
+}
+
+/// What happens when Dave loses a life but still has lives left.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RespawnMode {
+    /// Regenerate the level and start it over from the beginning.
+    Restart,
+    /// Respawn at the last checkpoint touched, with the level as it was then.
+    #[default]
+    Checkpoint,
+}
+
+/// A named difficulty setting bundling lives, jump forgiveness, hazard density and scoring.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 3] = [Preset::Easy, Preset::Normal, Preset::Hard];
+
+    /// The name used in `config.toml` and on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Easy => "easy",
+            Preset::Normal => "normal",
+            Preset::Hard => "hard",
+        }
+    }
+
+    /// Looks up a preset by name, ignoring case.
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Lives at the start of a game.
+    pub fn lives(self) -> i32 {
+        match self {
+            Preset::Easy => 5,
+            Preset::Normal => 3,
+            Preset::Hard => 2,
+        }
+    }
+
+    /// Multiplier for `coyote_time` and `jump_buffer_time`, so easier presets forgive
+    /// late and early jumps more.
+    pub fn jump_timing_mult(self) -> f32 {
+        match self {
+            Preset::Easy => 1.5,
+            Preset::Normal => 1.0,
+            Preset::Hard => 0.5,
+        }
+    }
+
+    /// Multiplier for every hazard chance in `[generator]`.
+    pub fn hazard_mult(self) -> f32 {
+        match self {
+            Preset::Easy => 0.5,
+            Preset::Normal => 1.0,
+            Preset::Hard => 1.5,
+        }
+    }
+
+    /// Multiplier for points scored, rewarding harder presets.
+    pub fn score_mult(self) -> f32 {
+        match self {
+            Preset::Easy => 0.5,
+            Preset::Normal => 1.0,
+            Preset::Hard => 2.0,
+        }
+    }
+
+    /// The preset after (or before) this one, wrapping around.
+    pub fn cycle(self, forward: bool) -> Self {
+        let i = Self::ALL.iter().position(|&p| p == self).unwrap_or(0);
+        let n = Self::ALL.len();
+        Self::ALL[if forward { (i + 1) % n } else { (i + n - 1) % n }]
+    }
+
+    /// `params` with its hazard chances scaled for this preset.
+    pub fn gen_params(self, params: &GenParams) -> GenParams {
+        let scale = |chance: u32| ((chance as f32 * self.hazard_mult()).round() as u32).min(100);
+        let mut params = params.clone();
+        params.first_level_floor_hazard_chance = scale(params.first_level_floor_hazard_chance);
+        params.floor_hazard_chance = scale(params.floor_hazard_chance);
+        params.platform_hazard_chance = scale(params.platform_hazard_chance);
+        params.difficulty.floor_hazard_chance = scale(params.difficulty.floor_hazard_chance);
+        params.difficulty.platform_hazard_chance = scale(params.difficulty.platform_hazard_chance);
+        params
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     /// Maximum level Dave can reach.
     #[serde(default = "default_max_level")]
     pub max_level: u32,
+    /// Difficulty preset.
+    #[serde(default)]
+    pub preset: Preset,
+    /// How Dave comes back after losing a life.
+    #[serde(default)]
+    pub respawn: RespawnMode,
     /// Physics-related constants.
+    #[serde(default)]
     pub physics: PhysicsConfig,
     /// Keyboard mapping configuration.
+    #[serde(default)]
     pub keys: KeysConfig,
+    /// Spring tile settings.
+    #[serde(default)]
+    pub spring: SpringConfig,
+    /// Conveyor belt tile settings.
+    #[serde(default)]
+    pub conveyor: ConveyorConfig,
+    /// Ice tile settings.
+    #[serde(default)]
+    pub ice: IceConfig,
+    /// Teleporter tile settings.
+    #[serde(default)]
+    pub teleporter: TeleporterConfig,
+    /// Warp zone settings.
+    #[serde(default)]
+    pub warp: WarpConfig,
+    /// Keyboard input settings.
+    #[serde(default)]
+    pub input: InputConfig,
+    /// Level generator settings.
+    #[serde(default)]
+    pub generator: GenParams,
 }
 
 fn default_max_level() -> u32 { 10 }
@@ -64,58 +408,320 @@ impl Default for Config {
     fn default() -> Self {
         Config {
             max_level: 10,
-            physics: PhysicsConfig {
-                target_vx: 30.0,
-                accel_ground: 200.0,
-                accel_air: 80.0,
-                jump_vy: -28.0,
-                gravity: 80.0,
-                coyote_time: 0.1,
-                jump_buffer_time: 0.1,
-                jump_release_gravity_mult: 3.0,
-                friction: 400.0,
-            },
-            keys: KeysConfig {
-                left: vec!["Left".to_string(), "a".to_string(), "A".to_string()],
-                right: vec!["Right".to_string(), "d".to_string(), "D".to_string()],
-                jump: vec!["Up".to_string(), "w".to_string(), "W".to_string(), "Space".to_string()],
-                quit: vec!["Esc".to_string(), "q".to_string(), "Q".to_string()],
-                restart: vec!["Enter".to_string()],
-            },
+            preset: Preset::default(),
+            respawn: RespawnMode::default(),
+            physics: PhysicsConfig::default(),
+            keys: KeysConfig::default(),
+            spring: SpringConfig::default(),
+            conveyor: ConveyorConfig::default(),
+            ice: IceConfig::default(),
+            teleporter: TeleporterConfig::default(),
+            warp: WarpConfig::default(),
+            input: InputConfig::default(),
+            generator: GenParams::default(),
+        }
+    }
+}
+
+/// A config file to read, as one layer of the configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigLayer {
+    pub path: PathBuf,
+    /// Whether the file was named explicitly, so a missing file is an error.
+    pub required: bool,
+}
+
+/// The config files to read, lowest priority first. Settings in later files override
+/// the same settings in earlier ones; anything no file sets keeps its default.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ConfigPaths {
+    pub layers: Vec<ConfigLayer>,
+}
+
+impl ConfigPaths {
+    /// Finds the config files for this process, with `cli_path` given by `--config`.
+    pub fn new(cli_path: Option<&Path>) -> Self {
+        Self::discover(cli_path, |name| env::var(name).ok())
+    }
+
+    /// Builds the layers from lowest to highest priority: system config directories
+    /// (`$XDG_CONFIG_DIRS`), the user config directory (`$XDG_CONFIG_HOME` or
+    /// `~/.config`), `config.toml` in the working directory, `$RUSTYDAVE_CONFIG` and
+    /// finally `cli_path`.
+    pub fn discover(cli_path: Option<&Path>, var: impl Fn(&str) -> Option<String>) -> Self {
+        let optional = |path: PathBuf| ConfigLayer { path, required: false };
+        let in_dir = |dir: &str| Path::new(dir).join("rustydave").join(CONFIG_PATH);
+        let mut layers = Vec::new();
+
+        let system_dirs = var("XDG_CONFIG_DIRS").filter(|d| !d.is_empty()).unwrap_or_else(|| "/etc/xdg".to_string());
+        // Earlier directories in the list take precedence, so they go on top
+        for dir in system_dirs.split(':').rev().filter(|d| !d.is_empty()) {
+            layers.push(optional(in_dir(dir)));
+        }
+        match var("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+            Some(dir) => layers.push(optional(in_dir(&dir))),
+            None => {
+                if let Some(home) = var("HOME").filter(|d| !d.is_empty()) {
+                    layers.push(optional(Path::new(&home).join(".config").join("rustydave").join(CONFIG_PATH)));
+                }
+            }
+        }
+        layers.push(optional(PathBuf::from(CONFIG_PATH)));
+        if let Some(path) = var(CONFIG_ENV).filter(|p| !p.is_empty()) {
+            layers.push(ConfigLayer { path: PathBuf::from(path), required: true });
+        }
+        if let Some(path) = cli_path {
+            layers.push(ConfigLayer { path: path.to_path_buf(), required: true });
+        }
+        ConfigPaths { layers }
+    }
+
+    /// Last-modified time of each layer, `None` for files that can't be read. Comparing
+    /// two of these tells whether any config file changed.
+    pub fn modified_times(&self) -> Vec<Option<SystemTime>> {
+        self.layers
+            .iter()
+            .map(|layer| fs::metadata(&layer.path).and_then(|m| m.modified()).ok())
+            .collect()
+    }
+
+    /// The file the options screen saves to: an explicitly named file if there is one,
+    /// otherwise the highest-priority file that exists, otherwise `config.toml` in the
+    /// working directory.
+    pub fn save_target(&self) -> PathBuf {
+        self.layers
+            .iter()
+            .rev()
+            .find(|layer| layer.required || layer.path.exists())
+            .map_or_else(|| PathBuf::from(CONFIG_PATH), |layer| layer.path.clone())
+    }
+}
+
+/// A problem found while loading `config.toml`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigIssue {
+    /// The setting (or the whole file) was ignored and defaults used instead.
+    Error(String),
+    /// The setting was loaded but probably doesn't do what was intended.
+    Warning(String),
+}
+
+impl ConfigIssue {
+    pub fn is_error(&self) -> bool {
+        matches!(self, ConfigIssue::Error(_))
+    }
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigIssue::Error(msg) => write!(f, "error: {}", msg),
+            ConfigIssue::Warning(msg) => write!(f, "warning: {}", msg),
         }
     }
 }
 
 impl Config {
-    /// Loads the configuration from `config.toml`, or returns defaults if loading fails.
-    pub fn load() -> Self {
-        fs::read_to_string("config.toml")
-            .and_then(|content| toml::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)))
-            .unwrap_or_else(|_| Config::default())
-    }
-
-    /// Checks if a given `KeyCode` matches any of the keys in the provided `key_list`.
-    pub fn key_matches(&self, code: KeyCode, key_list: &[String]) -> bool {
-        for k in key_list {
-            let matches = match k.as_str() {
-                "Left" => code == KeyCode::Left,
-                "Right" => code == KeyCode::Right,
-                "Up" => code == KeyCode::Up,
-                "Down" => code == KeyCode::Down,
-                "Enter" => code == KeyCode::Enter,
-                "Esc" => code == KeyCode::Esc,
-                "Space" => code == KeyCode::Char(' '),
-                s if s.len() == 1 => {
-                    let c = s.chars().next().unwrap();
-                    code == KeyCode::Char(c) || 
-                    code == KeyCode::Char(c.to_lowercase().next().unwrap()) || 
-                    code == KeyCode::Char(c.to_uppercase().next().unwrap())
-                },
-                _ => false,
-            };
-            if matches { return true; }
+    /// Loads the configuration from the files found by `ConfigPaths::new`, using
+    /// defaults for anything they don't set. Any problems are reported on stderr.
+    pub fn load(cli_path: Option<&Path>) -> Self {
+        let (config, issues) = Self::load_from(&ConfigPaths::new(cli_path));
+        for issue in &issues {
+            eprintln!("{}", issue);
+        }
+        config
+    }
+
+    /// Loads and merges every layer in `paths`, returning the problems found along the way.
+    /// Optional files that don't exist are skipped silently.
+    pub fn load_from(paths: &ConfigPaths) -> (Self, Vec<ConfigIssue>) {
+        let mut sources = Vec::new();
+        let mut issues = Vec::new();
+        for layer in &paths.layers {
+            match fs::read_to_string(&layer.path) {
+                Ok(text) => sources.push((layer.path.display().to_string(), text)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound && !layer.required => {}
+                Err(e) => issues.push(ConfigIssue::Error(format!("{}: {}", layer.path.display(), e))),
+            }
+        }
+        let (config, more) = Self::from_sources(&sources);
+        issues.extend(more);
+        (config, issues)
+    }
+
+    /// Parses the text of a single config file; see `from_sources`.
+    pub fn parse(text: &str) -> (Self, Vec<ConfigIssue>) {
+        Self::from_sources(&[(CONFIG_PATH.to_string(), text.to_string())])
+    }
+
+    /// Merges config files given as `(name, text)`, lowest priority first. A syntax or
+    /// type error discards that file; out-of-range physics values fall back to their
+    /// defaults; unrecognized key names are dropped; unknown settings and keys bound to
+    /// more than one action are reported as warnings.
+    pub fn from_sources(sources: &[(String, String)]) -> (Self, Vec<ConfigIssue>) {
+        let mut issues = Vec::new();
+        let known = toml::Table::try_from(Config::default()).expect("config always serializes");
+        let mut merged = known.clone();
+
+        for (name, text) in sources {
+            // Deserializing each file on its own gives errors a line and column
+            if let Err(e) = toml::from_str::<Config>(text) {
+                let location = match e.span() {
+                    Some(span) => {
+                        let (line, column) = line_col(text, span.start);
+                        format!("{}:{}:{}", name, line, column)
+                    }
+                    None => name.clone(),
+                };
+                issues.push(ConfigIssue::Error(format!("{}: {}; ignoring this file", location, e.message().trim_end())));
+                continue;
+            }
+            if let Ok(doc) = toml_edit::ImDocument::parse(text.as_str()) {
+                find_unknown_keys(name, text, doc.as_table(), &known, "", &mut issues);
+            }
+            if let Ok(table) = toml::from_str::<toml::Table>(text) {
+                merge_tables(&mut merged, table);
+            }
+        }
+
+        let mut config: Config = merged.try_into().unwrap_or_else(|e: toml::de::Error| {
+            issues.push(ConfigIssue::Error(format!("{}; using default settings", e.message().trim_end())));
+            Config::default()
+        });
+
+        for action in Action::ALL {
+            config.keys.binding_mut(action).retain(|key| {
+                let known = KeyBinding::parse(key).is_some();
+                if !known {
+                    issues.push(ConfigIssue::Error(format!("keys.{}: unrecognized key name `{}` is ignored", action.name(), key)));
+                }
+                known
+            });
+        }
+        for (key, actions) in ActionMap::new(&config.keys).conflicts() {
+            let names: Vec<&str> = actions.iter().map(|a| a.name()).collect();
+            issues.push(ConfigIssue::Warning(format!("keys: `{}` is bound to both {}", key, names.join(" and "))));
+        }
+
+        let defaults = PhysicsConfig::default();
+        for (name, requirement) in config.physics.out_of_range() {
+            let default = defaults.fields().into_iter().find(|(n, _)| *n == name).map_or(0.0, |(_, v)| v);
+            if let Some(value) = config.physics.field_mut(name) {
+                issues.push(ConfigIssue::Error(format!("physics.{} = {} {}; using {}", name, value, requirement, default)));
+                *value = default;
+            }
+        }
+        if config.max_level == 0 {
+            issues.push(ConfigIssue::Error("max_level must be at least 1; using 1".to_string()));
+            config.max_level = 1;
+        }
+        let input_defaults = InputConfig::default();
+        for (name, value, default) in [
+            ("press_hold", &mut config.input.press_hold, input_defaults.press_hold),
+            ("repeat_hold", &mut config.input.repeat_hold, input_defaults.repeat_hold),
+        ] {
+            if !(*value > 0.0 && *value <= 2.0) {
+                issues.push(ConfigIssue::Error(format!("input.{} = {} must be between 0 and 2 seconds; using {}", name, value, default)));
+                *value = default;
+            }
+        }
+
+        for problem in config.generator.problems() {
+            issues.push(ConfigIssue::Error(format!("generator.{} {}; using the default", problem.name, problem.requirement)));
+            problem.reset(&mut config.generator);
+        }
+
+        (config, issues)
+    }
+
+    /// The generator settings with the preset applied, as levels are generated.
+    pub fn gen_params(&self) -> GenParams {
+        self.preset.gen_params(&self.generator)
+    }
+
+    /// Writes the settings editable in game to `path`, keeping everything else in the
+    /// file (other sections, unknown entries and comments) as it was.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let existing = fs::read_to_string(path).unwrap_or_default();
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.merge_into(&existing))
+    }
+
+    /// Returns `existing` (the text of a config file) with `max_level`, `preset`,
+    /// `[physics]` and `[keys]` replaced by this configuration's values. Text that fails to parse is
+    /// replaced entirely.
+    pub fn merge_into(&self, existing: &str) -> String {
+        let mut doc = existing.parse::<DocumentMut>().unwrap_or_default();
+        let ours = toml::to_string(self)
+            .expect("config always serializes")
+            .parse::<DocumentMut>()
+            .expect("serialized config always parses");
+
+        doc["max_level"] = ours["max_level"].clone();
+        doc["preset"] = ours["preset"].clone();
+        for section in ["physics", "keys"] {
+            if !doc.contains_table(section) {
+                doc[section] = toml_edit::table();
+            }
+            let Some(table) = ours[section].as_table() else { continue };
+            for (key, item) in table.iter() {
+                let mut item = item.clone();
+                // Keep any comment attached to the old value
+                if let (Some(old), Some(new)) = (doc[section].get(key).and_then(|i| i.as_value()), item.as_value_mut()) {
+                    *new.decor_mut() = old.decor().clone();
+                }
+                doc[section][key] = item;
+            }
+        }
+        doc.to_string()
+    }
+}
+
+/// Converts a byte offset into `text` to a 1-based line and column.
+fn line_col(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+    (line, column)
+}
+
+/// Overlays `over` onto `base`, merging sections key by key.
+fn merge_tables(base: &mut toml::Table, over: toml::Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(over)) => merge_tables(base, over),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Warns about every key in `table` that has no counterpart in `known`, recursing into
+/// sections both have. `name` identifies the file `text` came from.
+fn find_unknown_keys(name: &str, text: &str, table: &toml_edit::Table, known: &toml::Table, prefix: &str, issues: &mut Vec<ConfigIssue>) {
+    for (key, item) in table.iter() {
+        let path = format!("{}{}", prefix, key);
+        match known.get(key) {
+            None => {
+                let location = match table.key(key).and_then(|k| k.span()) {
+                    Some(span) => {
+                        let (line, column) = line_col(text, span.start);
+                        format!("{}:{}:{}", name, line, column)
+                    }
+                    None => name.to_string(),
+                };
+                issues.push(ConfigIssue::Warning(format!("{}: unknown setting `{}` is ignored", location, path)));
+            }
+            Some(toml::Value::Table(known)) => {
+                if let Some(table) = item.as_table() {
+                    find_unknown_keys(name, text, table, known, &format!("{}.", path), issues);
+                }
+            }
+            Some(_) => {}
         }
-        false
     }
 }
 
@@ -134,6 +740,106 @@ pub enum Tile {
     Hazard,
     /// Collectible diamonds that grant 100 points each.
     Diamond,
+    /// One-way platform that is only solid from above; Dave can jump up through it
+    /// and drop down through it with Down + Jump.
+    Platform,
+    /// A colored key that opens all doors of the same color.
+    Key(KeyColor),
+    /// A colored door that is solid until Dave holds the matching key.
+    Door(KeyColor),
+    /// Floor that collapses a moment after Dave stands on it.
+    Crumbling,
+    /// A crumbling floor that has collapsed; it respawns after a while.
+    Collapsed,
+    /// A fire jet that cycles between harmless and deadly.
+    FireJet { active: bool },
+    /// A spring that launches Dave high into the air when he lands on it.
+    Spring,
+    /// A conveyor belt that carries Dave to the left.
+    ConveyorLeft,
+    /// A conveyor belt that carries Dave to the right.
+    ConveyorRight,
+    /// Slippery ice with reduced friction and acceleration.
+    Ice,
+    /// A teleporter that moves Dave to the other teleporter with the same id.
+    Teleporter(u8),
+    /// A hidden warp zone that skips ahead several levels.
+    Warp,
+    /// A checkpoint that records where Dave respawns after losing a life.
+    Checkpoint,
+}
+
+/// Colors used to pair keys with the doors they open.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum KeyColor {
+    Red,
+    Green,
+    Blue,
+}
+
+impl KeyColor {
+    /// All key colors, in the order keys are meant to be collected.
+    pub const ALL: [KeyColor; 3] = [KeyColor::Red, KeyColor::Green, KeyColor::Blue];
+
+    /// Human-readable name of the color.
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyColor::Red => "Red",
+            KeyColor::Green => "Green",
+            KeyColor::Blue => "Blue",
+        }
+    }
+}
+
+impl Tile {
+    /// Returns true if Dave can stand on top of this tile.
+    pub fn is_standable(self) -> bool {
+        matches!(self, Tile::Wall | Tile::Platform | Tile::Crumbling | Tile::Spring |
+                       Tile::ConveyorLeft | Tile::ConveyorRight | Tile::Ice)
+    }
+
+    /// Returns true if this tile is deadly, at least part of the time.
+    pub fn is_hazardous(self) -> bool {
+        matches!(self, Tile::Hazard | Tile::FireJet { .. })
+    }
+}
+
+/// A grid of tiles, indexed by row and then column: `level[y][x]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Level {
+    width: usize,
+    tiles: Vec<Tile>,
+}
+
+impl Level {
+    /// Creates an empty level of the given size.
+    pub fn new(width: usize, height: usize) -> Self {
+        Level { width, tiles: vec![Tile::Empty; width * height] }
+    }
+
+    /// Width in tiles.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height in tiles, or 0 for a level with no columns.
+    pub fn height(&self) -> usize {
+        self.tiles.len().checked_div(self.width).unwrap_or(0)
+    }
+}
+
+impl Index<usize> for Level {
+    type Output = [Tile];
+
+    fn index(&self, y: usize) -> &[Tile] {
+        &self.tiles[y * self.width..(y + 1) * self.width]
+    }
+}
+
+impl IndexMut<usize> for Level {
+    fn index_mut(&mut self, y: usize) -> &mut [Tile] {
+        &mut self.tiles[y * self.width..(y + 1) * self.width]
+    }
 }
 
 /// A simple, deterministic random number generator for level generation.
@@ -144,7 +850,17 @@ pub struct SimpleRng {
 impl SimpleRng {
     /// Creates a new `SimpleRng` with the given seed.
     pub fn new(seed: u32) -> Self {
-        let mut state = seed as u64 + 0x9E3779B97F4A7C15;
+        Self::mixed(seed as u64)
+    }
+
+    /// Creates the generator for one level of a run, so every (seed, level) pair
+    /// gets its own sequence.
+    pub fn for_level(seed: u32, level_index: u32) -> Self {
+        Self::mixed(((seed as u64) << 32 | level_index as u64) ^ 0x5851F42D4C957F2D)
+    }
+
+    fn mixed(seed: u64) -> Self {
+        let mut state = seed.wrapping_add(0x9E3779B97F4A7C15);
         // Basic mixing
         state = (state ^ (state >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
         state = (state ^ (state >> 27)).wrapping_mul(0x94D049BB133111EB);
@@ -153,7 +869,7 @@ impl SimpleRng {
     }
 
     /// Generates the next random 32-bit unsigned integer.
-    pub fn next(&mut self) -> u32 {
+    pub fn next_u32(&mut self) -> u32 {
         self.state = self.state.wrapping_mul(6364136223846793005).wrapping_add(1);
         (self.state >> 32) as u32
     }
@@ -161,80 +877,376 @@ impl SimpleRng {
     /// Generates a random 32-bit unsigned integer in the range [min, max).
     pub fn range(&mut self, min: u32, max: u32) -> u32 {
         if min >= max { return min; }
-        min + (self.next() % (max - min))
+        min + (self.next_u32() % (max - min))
     }
 }
 
-/// Generates a level grid and starting player position based on the level number.
-///
-/// # Arguments
-/// * `level_num` - The level number, used as a seed for procedural generation.
-///
-/// # Returns
-/// A tuple containing:
-/// * The 2D grid of `Tile` elements.
-/// * The starting (x, y) coordinates for the player.
-pub fn generate_level(level_num: u32) -> ([[Tile; LEVEL_WIDTH]; LEVEL_HEIGHT], (f32, f32)) {
-    let mut level = [[Tile::Empty; LEVEL_WIDTH]; LEVEL_HEIGHT];
-    
-    // Boundaries
-    for x in 0..LEVEL_WIDTH {
-        level[0][x] = Tile::Wall;
-        level[LEVEL_HEIGHT - 1][x] = Tile::Wall;
-    }
-    for y in 0..LEVEL_HEIGHT {
-        level[y][0] = Tile::Wall;
-        level[y][LEVEL_WIDTH - 1] = Tile::Wall;
-    }
+/// Overall layout of a level.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Archetype {
+    /// Four tiers of long ledges alternating from side to side.
+    ZigZag,
+    /// Tiers of short floating islands, some of them special surfaces.
+    Islands,
+    /// An open cave with irregular floors, grown by smoothing random rock.
+    Cave,
+    /// Zig-zag ledges stacked several screens high, with the exit at the top.
+    Tower,
+    /// Hand-made rooms from `chunks.txt`, fitted together edge to edge.
+    Chunks,
+    /// A run along a floor broken by bottomless pits, with ledges up to the trophy.
+    Pit,
+}
 
-    let mut rng = SimpleRng::new(level_num);
-    
-    let player_x = 2.0;
-    let player_y = 17.99; // Start on top of the base platform
+impl Archetype {
+    pub const ALL: [Archetype; 6] = [Archetype::ZigZag, Archetype::Islands, Archetype::Cave, Archetype::Tower, Archetype::Chunks, Archetype::Pit];
+}
 
-    // Base platform for player
-    for x in 1..10 { level[18][x] = Tile::Wall; }
+/// Settings that shape generated levels, read from `[generator]`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct GenParams {
+    /// Layouts to choose from; each level picks one at random.
+    pub archetypes: Vec<Archetype>,
+    /// Rows of the four platform tiers, from the bottom up. The trophy sits on the top one.
+    pub tier_heights: [usize; 4],
+    /// Number of attempts at placing a diamond; attempts that land on a bad spot are skipped.
+    pub diamond_attempts: u32,
+    /// Percent chance of starting a floor hazard at each free column on level 1.
+    pub first_level_floor_hazard_chance: u32,
+    /// Percent chance of starting a floor hazard at each free column after level 1.
+    pub floor_hazard_chance: u32,
+    /// Percent chance of starting a hazard at each free spot on a platform.
+    pub platform_hazard_chance: u32,
+    /// Shortest and longest floating island (the longest is exclusive).
+    pub island_length: [u32; 2],
+    /// Percent of a cave that starts out as rock, before smoothing.
+    pub cave_fill: u32,
+    /// Smoothing passes run over a cave; more passes give rounder, more open chambers.
+    pub cave_smoothing: u32,
+    /// How many screens high a tower is.
+    pub tower_screens: u32,
+    /// Dave's starting column, over the base platform (1 to just under 10).
+    pub spawn_x: f32,
+    /// Dave's starting row; just above 18 stands him on the base platform.
+    pub spawn_y: f32,
+    /// How the settings above get harder over the levels.
+    pub difficulty: DifficultyCurve,
+}
 
-    let heights = [16, 12, 8, 4];
-    let mut w1 = 0;
-    let mut w1_start = 15;
-    let mut w2 = 0;
-    let mut w3 = 0;
-    let mut w4 = 0;
+/// Values the generator settings move towards as levels go by, read from
+/// `[generator.difficulty]`. Level 1 uses the plain `[generator]` values, and each
+/// level moves an equal step closer until `ramp_levels`, where these caps apply.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct DifficultyCurve {
+    /// Level at which the caps are reached.
+    pub ramp_levels: u32,
+    /// Floor hazard chance at the cap.
+    pub floor_hazard_chance: u32,
+    /// Platform hazard chance at the cap.
+    pub platform_hazard_chance: u32,
+    /// Island lengths at the cap; shorter islands leave wider gaps.
+    pub island_length: [u32; 2],
+    /// Tiles cut from the end of each zig-zag ledge at the cap, widening the gap to the next tier.
+    pub ledge_shrink: u32,
+    /// Diamond placement attempts at the cap.
+    pub diamond_attempts: u32,
+}
 
-    if level_num % 2 != 0 {
-        // Archetype 1: Zig-zag (Classic)
-        // H16: Left to Rightish
-        w1_start = 15;
-        w1 = rng.range(35, 55) as usize;
-        for x in w1_start..w1 { level[16][x] = Tile::Wall; }
+impl Default for DifficultyCurve {
+    fn default() -> Self {
+        DifficultyCurve {
+            ramp_levels: 20,
+            floor_hazard_chance: 45,
+            platform_hazard_chance: 30,
+            island_length: [4, 9],
+            ledge_shrink: 5,
+            diamond_attempts: 4,
+        }
+    }
+}
 
-        // H12: Right to Leftish
-        w2 = rng.range(25, 45) as usize;
-        for x in w2..59 { level[12][x] = Tile::Wall; }
+impl Default for GenParams {
+    fn default() -> Self {
+        GenParams {
+            archetypes: Archetype::ALL.to_vec(),
+            tier_heights: [16, 12, 8, 4],
+            diamond_attempts: 8,
+            first_level_floor_hazard_chance: 10,
+            floor_hazard_chance: 30,
+            platform_hazard_chance: 15,
+            island_length: [5, 12],
+            cave_fill: 45,
+            cave_smoothing: 4,
+            tower_screens: 3,
+            spawn_x: 2.0,
+            spawn_y: 17.99,
+            difficulty: DifficultyCurve::default(),
+        }
+    }
+}
 
-        // H8: Left to Rightish
-        w3 = rng.range(35, 55) as usize;
-        for x in 1..w3 { level[8][x] = Tile::Wall; }
+/// Copies one setting from the defaults (the second argument) into the first.
+type Restore = fn(&mut GenParams, &GenParams);
 
-        // H4: Right to Leftish
-        w4 = rng.range(25, 45) as usize;
-        for x in w4..59 { level[4][x] = Tile::Wall; }
-    } else {
-        // Archetype 2: Floating Islands
-        for &h in &heights {
-            let num_islands = rng.range(2, 4);
+/// A `[generator]` setting that is out of range, as found by `GenParams::problems`.
+pub struct GenProblem {
+    /// The setting's name within `[generator]`.
+    pub name: &'static str,
+    /// What the setting must be.
+    pub requirement: &'static str,
+    restore: Restore,
+}
+
+impl GenProblem {
+    /// Puts the setting in `params` back to its default.
+    pub fn reset(&self, params: &mut GenParams) {
+        (self.restore)(params, &GenParams::default());
+    }
+}
+
+impl GenParams {
+    /// Checks the settings a level can't be built without, returning each one that is
+    /// out of range along with the way to reset it.
+    pub fn problems(&self) -> Vec<GenProblem> {
+        let mut problems = Vec::new();
+        let mut problem = |name, requirement, restore: Restore| problems.push(GenProblem { name, requirement, restore });
+        if self.archetypes.is_empty() {
+            problem("archetypes", "must list at least one archetype", |p, d| p.archetypes = d.archetypes.clone());
+        }
+        // Each tier must be a jump (at most 4 rows) above the one below, with headroom
+        // to stand under the next (3 rows; the base platform is offset sideways, so 2
+        // will do for the first tier), and leave room to jump onto the top one
+        let mut below = LEVEL_HEIGHT - 2;
+        let mut min_gap = 2;
+        let tiers_ok = self.tier_heights.iter().all(|&h| {
+            let ok = h < below && (min_gap..=4).contains(&(below - h));
+            below = h;
+            min_gap = 3;
+            ok
+        }) && below >= 3;
+        if !tiers_ok {
+            problem(
+                "tier_heights",
+                "must each be 3 or 4 rows above the one below (the lowest 2 to 4 above row 18), with the top tier at row 3 or below",
+                |p, d| p.tier_heights = d.tier_heights,
+            );
+        }
+        let chances: [(&str, u32, Restore); 6] = [
+            ("first_level_floor_hazard_chance", self.first_level_floor_hazard_chance, |p, d| p.first_level_floor_hazard_chance = d.first_level_floor_hazard_chance),
+            ("floor_hazard_chance", self.floor_hazard_chance, |p, d| p.floor_hazard_chance = d.floor_hazard_chance),
+            ("platform_hazard_chance", self.platform_hazard_chance, |p, d| p.platform_hazard_chance = d.platform_hazard_chance),
+            ("cave_fill", self.cave_fill, |p, d| p.cave_fill = d.cave_fill),
+            ("difficulty.floor_hazard_chance", self.difficulty.floor_hazard_chance, |p, d| p.difficulty.floor_hazard_chance = d.difficulty.floor_hazard_chance),
+            ("difficulty.platform_hazard_chance", self.difficulty.platform_hazard_chance, |p, d| p.difficulty.platform_hazard_chance = d.difficulty.platform_hazard_chance),
+        ];
+        for (name, chance, restore) in chances {
+            if chance > 100 {
+                problem(name, "is a percentage and must be at most 100", restore);
+            }
+        }
+        let lengths: [(&str, [u32; 2], Restore); 2] = [
+            ("island_length", self.island_length, |p, d| p.island_length = d.island_length),
+            ("difficulty.island_length", self.difficulty.island_length, |p, d| p.difficulty.island_length = d.difficulty.island_length),
+        ];
+        for (name, [shortest, longest], restore) in lengths {
+            if !(2..=15).contains(&shortest) || !(shortest..=15).contains(&longest) {
+                problem(name, "must be two lengths from 2 to 15, shortest first", restore);
+            }
+        }
+        if self.cave_smoothing > 10 {
+            problem("cave_smoothing", "must be at most 10", |p, d| p.cave_smoothing = d.cave_smoothing);
+        }
+        if !(2..=5).contains(&self.tower_screens) {
+            problem("tower_screens", "must be from 2 to 5", |p, d| p.tower_screens = d.tower_screens);
+        }
+        if self.difficulty.ramp_levels == 0 {
+            problem("difficulty.ramp_levels", "must be at least 1", |p, d| p.difficulty.ramp_levels = d.difficulty.ramp_levels);
+        }
+        // Longer cuts leave no room for the ice patch on the second tier
+        if self.difficulty.ledge_shrink > 6 {
+            problem("difficulty.ledge_shrink", "must be at most 6", |p, d| p.difficulty.ledge_shrink = d.difficulty.ledge_shrink);
+        }
+        // Dave starts in the open row above the base platform at row 18, columns 1 to 9,
+        // so he always has a free cell and ground beneath him
+        if !(1.0..10.0).contains(&self.spawn_x) {
+            problem("spawn_x", "must be at least 1 and below 10, over the base platform", |p, d| p.spawn_x = d.spawn_x);
+        }
+        if !(17.0..18.0).contains(&self.spawn_y) {
+            problem("spawn_y", "must be at least 17 and below 18, on the base platform", |p, d| p.spawn_y = d.spawn_y);
+        }
+        problems
+    }
+
+    /// How far along the difficulty curve level `level_index` is, from 0 at level 1
+    /// to 1 at `difficulty.ramp_levels` and beyond.
+    pub fn progress(&self, level_index: u32) -> f32 {
+        let steps = self.difficulty.ramp_levels.saturating_sub(1).max(1);
+        (level_index.saturating_sub(1) as f32 / steps as f32).min(1.0)
+    }
+
+    /// The settings for level `level_index`, moved along the difficulty curve.
+    pub fn at_level(&self, level_index: u32) -> GenParams {
+        let curve = &self.difficulty;
+        let t = self.progress(level_index);
+        let step = |from: u32, to: u32| (from as f32 + (to as f32 - from as f32) * t).round() as u32;
+        GenParams {
+            diamond_attempts: step(self.diamond_attempts, curve.diamond_attempts),
+            floor_hazard_chance: step(self.floor_hazard_chance, curve.floor_hazard_chance),
+            platform_hazard_chance: step(self.platform_hazard_chance, curve.platform_hazard_chance),
+            island_length: [
+                step(self.island_length[0], curve.island_length[0]),
+                step(self.island_length[1], curve.island_length[1]),
+            ],
+            ..self.clone()
+        }
+    }
+}
+
+/// Generates level `level_num` of the run with seed 0, using the default `GenParams`.
+pub fn generate_level(level_num: u32) -> (Level, (f32, f32)) {
+    generate_level_with(0, level_num, &GenParams::default())
+}
+
+/// Generates a level grid and starting player position.
+///
+/// # Arguments
+/// * `seed` - The master seed of the run; with the level index it fixes the layout.
+/// * `level_index` - The level number (from 1), which decides which features appear.
+/// * `params` - Settings that shape the level.
+///
+/// # Returns
+/// A tuple containing:
+/// * The 2D grid of `Tile` elements.
+/// * The starting (x, y) coordinates for the player.
+pub fn generate_level_with(seed: u32, level_index: u32, params: &GenParams) -> (Level, (f32, f32)) {
+    let (level, start, _) = generate_level_and_archetype(seed, level_index, params);
+    (level, start)
+}
+
+/// Like `generate_level_with`, but also returns the archetype the level was built
+/// as. A cave or chunk level that can't be finished falls back to a zig-zag one.
+pub fn generate_level_and_archetype(seed: u32, level_index: u32, params: &GenParams) -> (Level, (f32, f32), Archetype) {
+    let mut level = Level::new(LEVEL_WIDTH, LEVEL_HEIGHT);
+    
+    // Boundaries
+    for x in 0..LEVEL_WIDTH {
+        level[0][x] = Tile::Wall;
+        level[LEVEL_HEIGHT - 1][x] = Tile::Wall;
+    }
+    for y in 0..LEVEL_HEIGHT {
+        level[y][0] = Tile::Wall;
+        level[y][LEVEL_WIDTH - 1] = Tile::Wall;
+    }
+
+    let mut rng = SimpleRng::for_level(seed, level_index);
+    // Later levels have more hazards, shorter ledges and islands, and fewer diamonds
+    let shrink = (params.difficulty.ledge_shrink as f32 * params.progress(level_index)).round() as usize;
+    let params = &params.at_level(level_index);
+    
+    let player_x = params.spawn_x;
+    let player_y = params.spawn_y;
+
+    let mut archetype = match params.archetypes.len() {
+        0 => Archetype::ZigZag,
+        n => params.archetypes[rng.range(0, n as u32) as usize],
+    };
+    if archetype == Archetype::Cave {
+        match generate_cave(&mut rng, level_index, params) {
+            Some((cave, start)) => return (cave, start, archetype),
+            // Carving couldn't join up this cave; fall back to the classic layout
+            None => archetype = Archetype::ZigZag,
+        }
+    }
+    if archetype == Archetype::Chunks {
+        match chunks::generate_chunks(&mut rng, level_index, params) {
+            Some((rooms, start)) => return (rooms, start, archetype),
+            // No layout of the rooms could be finished; fall back to the classic layout
+            None => archetype = Archetype::ZigZag,
+        }
+    }
+    if archetype == Archetype::Tower {
+        let (tower, start) = generate_tower(&mut rng, level_index, params, shrink);
+        return (tower, start, archetype);
+    }
+    if archetype == Archetype::Pit {
+        let (pit, start) = generate_pit(&mut rng, level_index, params, shrink);
+        return (pit, start, archetype);
+    }
+
+    // Base platform for player
+    for x in 1..10 { level[18][x] = Tile::Wall; }
+
+    let heights = params.tier_heights;
+    let [h1, h2, h3, h4] = heights;
+    let mut w1 = 0;
+    let mut w1_start = 15;
+    let mut w2 = 0;
+    let mut w3 = 0;
+    let mut w4 = 0;
+
+    if archetype == Archetype::ZigZag {
+        // Archetype 1: Zig-zag (Classic)
+        // Tier 1: Left to Rightish
+        w1_start = 15;
+        w1 = rng.range(35, 55) as usize - shrink;
+        for x in w1_start..w1 { level[h1][x] = Tile::Wall; }
+
+        // Tier 2: Right to Leftish
+        w2 = rng.range(25, 45) as usize + shrink;
+        for x in w2..59 { level[h2][x] = Tile::Wall; }
+
+        // Tier 3: Left to Rightish
+        w3 = rng.range(35, 55) as usize - shrink;
+        for x in 1..w3 { level[h3][x] = Tile::Wall; }
+
+        // Tier 4: Right to Leftish
+        w4 = rng.range(25, 45) as usize + shrink;
+        for x in w4..59 { level[h4][x] = Tile::Wall; }
+
+        // The free end of each upper tier, over the jump point on the tier below, is a
+        // one-way platform Dave can jump up through
+        for x in w2..w2 + 3 { level[h2][x] = Tile::Platform; }
+        for x in w3 - 3..w3 { level[h3][x] = Tile::Platform; }
+        for x in w4..w4 + 3 { level[h4][x] = Tile::Platform; }
+
+        if level_index >= 5 {
+            // A short crumbling stretch on tier 3, away from the jump points
+            let bridge = rng.range(8, (w3.min(w4) - 8) as u32) as usize;
+            for x in bridge..bridge + 4 { level[h3][x] = Tile::Crumbling; }
+        }
+        if level_index >= 7 {
+            // A patch of ice on tier 2
+            let ice_start = rng.range(w2 as u32 + 3, 50) as usize;
+            for x in ice_start..ice_start + 5 { level[h2][x] = Tile::Ice; }
+        }
+    } else {
+        // Archetype 2: Floating Islands
+        for &h in &heights {
+            let num_islands = rng.range(2, 4);
             for i in 0..num_islands {
                 let start = rng.range(5 + i * 15, 15 + i * 15) as usize;
-                let len = rng.range(5, 12) as usize;
+                let len = rng.range(params.island_length[0], params.island_length[1]) as usize;
+                // Some of the upper islands are one-way platforms Dave can jump up through,
+                // and on later levels some crumble away under him
+                let tile = match rng.range(0, 6) {
+                    _ if h == h1 => Tile::Wall,
+                    0 | 1 => Tile::Platform,
+                    2 if level_index >= 5 => Tile::Crumbling,
+                    3 if level_index >= 7 => Tile::Ice,
+                    4 if level_index >= 8 => if rng.range(0, 2) == 0 { Tile::ConveyorLeft } else { Tile::ConveyorRight },
+                    _ => Tile::Wall,
+                };
                 for x in start..(start + len).min(59) {
-                    level[h][x] = Tile::Wall;
+                    level[h][x] = tile;
                 }
                 // Record some values for Trophy/Exit logic below if needed
-                if h == 16 && i == 0 { w1 = start + len; w1_start = start; }
-                if h == 12 && i == 0 { w2 = start; }
-                if h == 8 && i == 0 { w3 = start + len; }
-                if h == 4 && i == 0 { w4 = start; }
+                if h == h1 && i == 0 { w1 = start + len; w1_start = start; }
+                if h == h2 && i == 0 { w2 = start; }
+                if h == h3 && i == 0 { w3 = start + len; }
+                if h == h4 && i == 0 { w4 = start; }
             }
         }
         // Ensure some reasonable values for subsequent logic
@@ -247,7 +1259,7 @@ pub fn generate_level(level_num: u32) -> ([[Tile; LEVEL_WIDTH]; LEVEL_HEIGHT], (
     // Trophy: on the top platform
     let mut trophy_candidates = Vec::new();
     for x in 1..LEVEL_WIDTH - 1 {
-        if level[4][x] == Tile::Wall {
+        if level[h4][x].is_standable() {
             trophy_candidates.push(x);
         }
     }
@@ -258,29 +1270,30 @@ pub fn generate_level(level_num: u32) -> ([[Tile; LEVEL_WIDTH]; LEVEL_HEIGHT], (
         // Fallback for safety
         rng.range(w4 as u32 + 2, 58) as usize
     };
-    level[3][trophy_x] = Tile::Trophy;
+    let trophy_y = h4 - 1;
+    level[trophy_y][trophy_x] = Tile::Trophy;
 
-    // Exit: far right of H16 or Ground
+    // Exit: far right of tier 1 or Ground
     let (exit_x, exit_y) = if rng.range(0, 2) == 0 {
         level[18][55] = Tile::Exit;
         (55, 18)
     } else {
         let ex = (w1 - 2).max(w1_start).min(58);
-        level[15][ex] = Tile::Exit;
-        (ex, 15)
+        level[h1 - 1][ex] = Tile::Exit;
+        (ex, h1 - 1)
     };
 
     // Diamonds placement
-    for _ in 0..8 {
+    for _ in 0..params.diamond_attempts {
         let h = heights[rng.range(0, heights.len() as u32) as usize];
         let dx = rng.range(2, 58) as usize;
-        if level[h][dx] == Tile::Wall && level[h-1][dx] == Tile::Empty {
+        if level[h][dx].is_standable() && level[h-1][dx] == Tile::Empty {
             level[h-1][dx] = Tile::Diamond;
         }
     }
 
     // Hazards on floor
-    let floor_chance = if level_num == 1 { 10 } else { 30 };
+    let floor_chance = if level_index == 1 { params.first_level_floor_hazard_chance } else { params.floor_hazard_chance };
     let mut last_floor_hazard_end: i32 = -10;
     for x in 15..50usize {
         // Keep some columns safe on the floor to allow traversal/recovery
@@ -308,7 +1321,7 @@ pub fn generate_level(level_num: u32) -> ([[Tile; LEVEL_WIDTH]; LEVEL_HEIGHT], (
                     let mut count = 0;
                     for i in 0..15 {
                         let check_x = window_start + i;
-                        if check_x < LEVEL_WIDTH && ((check_x >= x && check_x < x + actual_size) || level[LEVEL_HEIGHT - 1][check_x] == Tile::Hazard) {
+                        if check_x < LEVEL_WIDTH && ((check_x >= x && check_x < x + actual_size) || level[LEVEL_HEIGHT - 1][check_x].is_hazardous()) {
                             count += 1;
                         }
                     }
@@ -329,23 +1342,22 @@ pub fn generate_level(level_num: u32) -> ([[Tile; LEVEL_WIDTH]; LEVEL_HEIGHT], (
     }
 
     // Hazards on platforms (placed on top of the walls)
-    let heights = [16, 12, 8, 4];
     for &h in &heights {
         let mut last_hazard_end: i32 = -10;
         for x in 5..55usize {
             let is_critical = |cx: usize| {
-                (h == 16 && (cx >= w2.max(w1_start).saturating_sub(2) && cx <= w2.max(w1_start) + 2)) || // H16 -> H12 jump point
-                (h == 12 && (cx >= w2.max(w1_start).saturating_sub(2) && cx <= w2.max(w1_start) + 2)) || // H16 -> H12 landing
-                (h == 12 && (cx >= w3.saturating_sub(2) && cx <= w3 + 2)) || // H12 -> H8 jump point
-                (h == 8 && (cx >= w3.saturating_sub(2) && cx <= w3 + 2)) ||  // H12 -> H8 landing
-                (h == 8 && (cx >= w4.saturating_sub(2) && cx <= w4 + 2)) ||  // H8 -> H4 jump point
-                (h == 4 && (cx >= w4.saturating_sub(2) && cx <= w4 + 2)) ||  // H8 -> H4 landing
-                (h == 4 && (cx >= trophy_x.saturating_sub(1) && cx <= trophy_x + 1)) ||
+                (h == h1 && (cx >= w2.max(w1_start).saturating_sub(2) && cx <= w2.max(w1_start) + 2)) || // Tier 1 -> 2 jump point
+                (h == h2 && (cx >= w2.max(w1_start).saturating_sub(2) && cx <= w2.max(w1_start) + 2)) || // Tier 1 -> 2 landing
+                (h == h2 && (cx >= w3.saturating_sub(2) && cx <= w3 + 2)) || // Tier 2 -> 3 jump point
+                (h == h3 && (cx >= w3.saturating_sub(2) && cx <= w3 + 2)) || // Tier 2 -> 3 landing
+                (h == h3 && (cx >= w4.saturating_sub(2) && cx <= w4 + 2)) || // Tier 3 -> 4 jump point
+                (h == h4 && (cx >= w4.saturating_sub(2) && cx <= w4 + 2)) || // Tier 3 -> 4 landing
+                (h == h4 && (cx >= trophy_x.saturating_sub(1) && cx <= trophy_x + 1)) ||
                 (h == exit_y + 1 && (cx >= exit_x.saturating_sub(1) && cx <= exit_x + 1))
             };
 
             let check_valid = |cx: usize| {
-                cx >= 5 && cx < 55 && 
+                (5..55).contains(&cx) &&
                 !is_critical(cx) && 
                 level[h][cx] == Tile::Wall && 
                 level[h][cx-1] == Tile::Wall && 
@@ -356,7 +1368,7 @@ pub fn generate_level(level_num: u32) -> ([[Tile; LEVEL_WIDTH]; LEVEL_HEIGHT], (
                 continue;
             }
 
-            if rng.range(0, 100) < 15 {
+            if rng.range(0, 100) < params.platform_hazard_chance {
                 let size = if rng.range(0, 2) == 0 { 1 } else { 2 };
                 let mut actual_size: usize = 0;
                 for k in 0..size {
@@ -373,7 +1385,7 @@ pub fn generate_level(level_num: u32) -> ([[Tile; LEVEL_WIDTH]; LEVEL_HEIGHT], (
                         let mut count = 0;
                         for i in 0..15 {
                             let check_x = window_start + i;
-                            if check_x < LEVEL_WIDTH && ((check_x >= x && check_x < x + actual_size) || level[h-1][check_x] == Tile::Hazard) {
+                            if check_x < LEVEL_WIDTH && ((check_x >= x && check_x < x + actual_size) || level[h-1][check_x].is_hazardous()) {
                                 count += 1;
                             }
                         }
@@ -384,8 +1396,14 @@ pub fn generate_level(level_num: u32) -> ([[Tile; LEVEL_WIDTH]; LEVEL_HEIGHT], (
                     }
 
                     if !violation {
+                        // Later levels swap some spikes for fire jets that switch on and off
+                        let hazard = if level_index >= 4 && rng.range(0, 3) == 0 {
+                            Tile::FireJet { active: false }
+                        } else {
+                            Tile::Hazard
+                        };
                         for k in 0..actual_size {
-                            level[h-1][x + k] = Tile::Hazard;
+                            level[h-1][x + k] = hazard;
                         }
                         last_hazard_end = (x + actual_size - 1) as i32;
                     }
@@ -393,6 +1411,1193 @@ pub fn generate_level(level_num: u32) -> ([[Tile; LEVEL_WIDTH]; LEVEL_HEIGHT], (
             }
         }
     }
-    
+
+    // Springs set into the floor on later levels
+    if level_index >= 9 {
+        let candidates: Vec<usize> = (12..50)
+            .filter(|&x| level[LEVEL_HEIGHT - 1][x - 1..=x + 1].iter().all(|&t| t == Tile::Wall))
+            .filter(|&x| level[LEVEL_HEIGHT - 2][x] == Tile::Empty)
+            .collect();
+        if !candidates.is_empty() {
+            let x = candidates[rng.range(0, candidates.len() as u32) as usize];
+            level[LEVEL_HEIGHT - 1][x] = Tile::Spring;
+        }
+    }
+
+    let marks = Landmarks {
+        start: (player_x as usize, player_y as usize),
+        trophy: (trophy_x, trophy_y),
+        exit: (exit_x, exit_y),
+    };
+    place_teleporters(&mut level, &mut rng, level_index, &marks);
+
+    // A hidden warp zone tucked against the ceiling above the top tier
+    let warp_y = h4.saturating_sub(3).max(1);
+    if level_index >= 2 && rng.range(0, 4) == 0 {
+        let columns: Vec<usize> = (2..LEVEL_WIDTH - 2)
+            .filter(|&x| level[h4][x].is_standable() && x.abs_diff(trophy_x) > 1 && level[warp_y][x] == Tile::Empty)
+            .collect();
+        if !columns.is_empty() {
+            let x = columns[rng.range(0, columns.len() as u32) as usize];
+            level[warp_y][x] = Tile::Warp;
+        }
+    }
+
+    let held = place_keys(&mut level, &mut rng, level_index, &marks);
+    // A checkpoint partway up the level, on the second or third tier
+    place_checkpoint(&mut level, &mut rng, level_index, &marks, &held, |y| y == h3 - 1 || y == h2 - 1);
+
+    (level, (player_x, player_y), archetype)
+}
+
+/// Builds a cave level: random rock smoothed into chambers by a cellular automaton,
+/// with passages carved wherever the trophy or exit can't be reached. Returns `None`
+/// if carving can't join them up, so the caller can fall back to another layout.
+fn generate_cave(rng: &mut SimpleRng, level_index: u32, params: &GenParams) -> Option<(Level, (f32, f32))> {
+    // Random rock, then smoothing passes: a cell turns to rock when more than half its
+    // neighbours are rock and opens up when fewer than half are. The edges stay rock.
+    let mut rock = [[true; LEVEL_WIDTH]; LEVEL_HEIGHT];
+    for row in &mut rock[1..LEVEL_HEIGHT - 1] {
+        for cell in &mut row[1..LEVEL_WIDTH - 1] {
+            *cell = rng.range(0, 100) < params.cave_fill;
+        }
+    }
+    for _ in 0..params.cave_smoothing {
+        let before = rock;
+        for (y, row) in rock.iter_mut().enumerate().take(LEVEL_HEIGHT - 1).skip(1) {
+            for (x, cell) in row.iter_mut().enumerate().take(LEVEL_WIDTH - 1).skip(1) {
+                let neighbours = (y - 1..=y + 1)
+                    .flat_map(|ny| (x - 1..=x + 1).map(move |nx| (nx, ny)))
+                    .filter(|&(nx, ny)| (nx, ny) != (x, y) && before[ny][nx])
+                    .count();
+                if neighbours > 4 {
+                    *cell = true;
+                } else if neighbours < 4 {
+                    *cell = false;
+                }
+            }
+        }
+    }
+
+    // An open pocket around the spawn point, with a floor to stand on
+    let start = (params.spawn_x as usize, params.spawn_y as usize);
+    let pocket = start.0.saturating_sub(1).max(1)..=(start.0 + 3).min(LEVEL_WIDTH - 2);
+    for row in &mut rock[start.1.saturating_sub(3).max(1)..=start.1] {
+        row[pocket.clone()].fill(false);
+    }
+    rock[start.1 + 1][pocket].fill(true);
+
+    let mut level = Level::new(LEVEL_WIDTH, LEVEL_HEIGHT);
+    for y in 0..LEVEL_HEIGHT {
+        for x in 0..LEVEL_WIDTH {
+            if rock[y][x] {
+                level[y][x] = Tile::Wall;
+            }
+        }
+    }
+    // Trophy: on a ledge near the roof, far enough left of the exit that their doors
+    // can't meet. If the rock left no ledge up there, cut one.
+    let candidates = floor_spots(&level, 2..43, 2..=7);
+    let trophy = if candidates.is_empty() {
+        let (x, y) = (rng.range(10, 40) as usize, rng.range(3, 8) as usize);
+        for lx in x - 1..=x + 1 {
+            level[y + 1][lx] = Tile::Wall;
+        }
+        level[y][x] = Tile::Empty;
+        level[y - 1][x] = Tile::Empty;
+        (x, y)
+    } else {
+        candidates[rng.range(0, candidates.len() as u32) as usize]
+    };
+    level[trophy.1][trophy.0] = Tile::Trophy;
+
+    // Exit: on any floor towards the right-hand side, or the bottom of the cave
+    let candidates = floor_spots(&level, 45..58, 2..=LEVEL_HEIGHT - 2);
+    let exit = if candidates.is_empty() {
+        level[LEVEL_HEIGHT - 3][55] = Tile::Empty;
+        (55, LEVEL_HEIGHT - 2)
+    } else {
+        candidates[rng.range(0, candidates.len() as u32) as usize]
+    };
+    level[exit.1][exit.0] = Tile::Exit;
+
+    // Carve passages until the trophy can be reached from the start and the exit
+    // from the trophy. Each passage can block another, so check again after carving.
+    let mut joined = false;
+    for _ in 0..6 {
+        let mut carved = false;
+        for (from, to) in [(start, trophy), (trophy, exit)] {
+            if !is_reachable(&level, from, to, &[]) {
+                carve_passage(&mut level, from, to, start);
+                carved = true;
+            }
+        }
+        if !carved {
+            joined = true;
+            break;
+        }
+    }
+    if !joined {
+        return None;
+    }
+
+    let marks = Landmarks { start, trophy, exit };
+    place_diamonds(&mut level, rng, params, &marks);
+    place_floor_hazards(&mut level, rng, level_index, params, &marks, |_, _| false);
+    place_teleporters(&mut level, rng, level_index, &marks);
+    let held = place_keys(&mut level, rng, level_index, &marks);
+    // A checkpoint somewhere in the middle rows of the cave
+    place_checkpoint(&mut level, rng, level_index, &marks, &held, |y| (6..=14).contains(&y));
+
+    Some((level, (params.spawn_x, params.spawn_y)))
+}
+
+/// Builds a tower level `tower_screens` screens high: zig-zag ledges stacked from the
+/// base platform to the top, with the trophy on the second-highest ledge and the exit
+/// at the far end of the highest. The lowest four ledges sit where a zig-zag level
+/// puts its tiers.
+fn generate_tower(rng: &mut SimpleRng, level_index: u32, params: &GenParams, shrink: usize) -> (Level, (f32, f32)) {
+    let height = LEVEL_HEIGHT * params.tower_screens as usize;
+    let mut level = Level::new(LEVEL_WIDTH, height);
+    for x in 0..LEVEL_WIDTH {
+        level[0][x] = Tile::Wall;
+        level[height - 1][x] = Tile::Wall;
+    }
+    for y in 0..height {
+        level[y][0] = Tile::Wall;
+        level[y][LEVEL_WIDTH - 1] = Tile::Wall;
+    }
+
+    // Base platform for the player, at the bottom of the tower
+    let base = height - 2;
+    for x in 1..10 { level[base][x] = Tile::Wall; }
+    let player_x = params.spawn_x;
+    let player_y = params.spawn_y + (height - LEVEL_HEIGHT) as f32;
+
+    // Ledges every four rows, alternating between the left and right walls. Each
+    // ledge's free end reaches past the one above, which is where Dave jumps up.
+    let tiers: Vec<usize> = (0..).map(|i| base - 2 - 4 * i).take_while(|&h| h >= 4).collect();
+    let ends: Vec<usize> = (0..tiers.len())
+        .map(|i| if i % 2 == 0 { rng.range(35, 55) as usize - shrink } else { rng.range(25, 45) as usize + shrink })
+        .collect();
+    for (i, &h) in tiers.iter().enumerate() {
+        let span = match i {
+            0 => 15..ends[0],
+            _ if i % 2 == 0 => 1..ends[i],
+            _ => ends[i]..LEVEL_WIDTH - 1,
+        };
+        for x in span { level[h][x] = Tile::Wall; }
+        // Above the lowest ledge, the free end is a one-way platform, as on a zig-zag tier
+        let free_end = match i {
+            0 => continue,
+            _ if i % 2 == 0 => ends[i] - 3..ends[i],
+            _ => ends[i]..ends[i] + 3,
+        };
+        for x in free_end { level[h][x] = Tile::Platform; }
+    }
+    for i in 1..tiers.len().saturating_sub(1) {
+        if i % 2 == 0 && level_index >= 5 && rng.range(0, 2) == 0 {
+            // A short crumbling stretch, away from the jump points
+            let bridge = rng.range(8, (ends[i].min(ends[i + 1]) - 8) as u32) as usize;
+            for x in bridge..bridge + 4 { level[tiers[i]][x] = Tile::Crumbling; }
+        }
+        if i % 2 == 1 && level_index >= 7 && rng.range(0, 2) == 0 {
+            let ice_start = rng.range(ends[i] as u32 + 3, 50) as usize;
+            for x in ice_start..ice_start + 5 { level[tiers[i]][x] = Tile::Ice; }
+        }
+    }
+
+    // Trophy: on the second-highest ledge
+    let top = tiers.len() - 1;
+    let trophy_candidates: Vec<usize> = (1..LEVEL_WIDTH - 1)
+        .filter(|&x| level[tiers[top - 1]][x] == Tile::Wall)
+        .collect();
+    let trophy_x = trophy_candidates[rng.range(0, trophy_candidates.len() as u32) as usize];
+    let trophy = (trophy_x, tiers[top - 1] - 1);
+    level[trophy.1][trophy.0] = Tile::Trophy;
+
+    // Exit: against the wall at the far end of the top ledge
+    let exit = (if top.is_multiple_of(2) { 2 } else { LEVEL_WIDTH - 3 }, tiers[top] - 1);
+    level[exit.1][exit.0] = Tile::Exit;
+
+    let marks = Landmarks {
+        start: (player_x as usize, player_y as usize),
+        trophy,
+        exit,
+    };
+    place_diamonds(&mut level, rng, params, &marks);
+    // Keep hazards off the jump points at either end of each climb
+    place_floor_hazards(&mut level, rng, level_index, params, &marks, |x, y| {
+        tiers.iter().position(|&h| h == y + 1).is_some_and(|i| {
+            x.abs_diff(ends[i]) <= 2 || ends.get(i + 1).is_some_and(|&end| x.abs_diff(end) <= 2)
+        })
+    });
+    place_teleporters(&mut level, rng, level_index, &marks);
+    let held = place_keys(&mut level, rng, level_index, &marks);
+    // A checkpoint partway up the tower
+    place_checkpoint(&mut level, rng, level_index, &marks, &held, |y| (height / 3..height * 2 / 3).contains(&y));
+
     (level, (player_x, player_y))
 }
+
+/// Builds a pit level: a run along the floor over bottomless pits that widen along
+/// the difficulty curve, with a ledge on each of the two lowest tiers leading up to
+/// the trophy, and the exit on solid ground at the far end.
+fn generate_pit(rng: &mut SimpleRng, level_index: u32, params: &GenParams, shrink: usize) -> (Level, (f32, f32)) {
+    let mut level = Level::new(LEVEL_WIDTH, LEVEL_HEIGHT);
+    for x in 0..LEVEL_WIDTH {
+        level[0][x] = Tile::Wall;
+        level[LEVEL_HEIGHT - 1][x] = Tile::Wall;
+    }
+    for y in 0..LEVEL_HEIGHT {
+        level[y][0] = Tile::Wall;
+        level[y][LEVEL_WIDTH - 1] = Tile::Wall;
+    }
+
+    // Base platform for the player
+    for x in 1..10 { level[18][x] = Tile::Wall; }
+    let start = (params.spawn_x as usize, params.spawn_y as usize);
+
+    // Pits through the floor, each followed by a solid stretch to land on. The far
+    // end of the floor stays solid for the exit.
+    let floor = LEVEL_HEIGHT - 1;
+    let mut pits = Vec::new();
+    let mut x = rng.range(12, 16) as usize;
+    loop {
+        let width = (rng.range(2, 4) as usize + shrink / 2).min(MAX_PIT_WIDTH);
+        if x + width > 50 {
+            break;
+        }
+        for px in x..x + width { level[floor][px] = Tile::Empty; }
+        pits.push(x..x + width);
+        x += width + rng.range(4, 9) as usize;
+    }
+
+    // A ledge on the first tier, and one on the second reaching past it on the right
+    let [h1, h2, _, _] = params.tier_heights;
+    let [min_len, max_len] = params.island_length;
+    let low = rng.range(14, 36) as usize;
+    let low_end = low + rng.range(min_len, max_len) as usize;
+    for x in low..low_end { level[h1][x] = Tile::Wall; }
+    let high = low + rng.range(3, 8) as usize;
+    let high_end = (high + rng.range(min_len, max_len) as usize).min(LEVEL_WIDTH - 2);
+    for x in high..high_end { level[h2][x] = Tile::Wall; }
+
+    // Trophy: on the upper ledge, clear of its ends
+    let trophy = (rng.range(high as u32 + 1, high_end as u32 - 1) as usize, h2 - 1);
+    level[trophy.1][trophy.0] = Tile::Trophy;
+    let exit = (55, LEVEL_HEIGHT - 2);
+    level[exit.1][exit.0] = Tile::Exit;
+
+    let marks = Landmarks { start, trophy, exit };
+    place_diamonds(&mut level, rng, params, &marks);
+    // A diamond hangs over some of the pits, in reach of a jump across
+    for pit in &pits {
+        if rng.range(0, 3) == 0 && level[LEVEL_HEIGHT - 3][pit.start + pit.len() / 2] == Tile::Empty {
+            level[LEVEL_HEIGHT - 3][pit.start + pit.len() / 2] = Tile::Diamond;
+        }
+    }
+    // Keep hazards off the floor where Dave takes off and lands around each pit
+    place_floor_hazards(&mut level, rng, level_index, params, &marks, |x, y| {
+        y == LEVEL_HEIGHT - 2 && pits.iter().any(|pit| x + 2 >= pit.start && x <= pit.end + 1)
+    });
+    place_teleporters(&mut level, rng, level_index, &marks);
+    let held = place_keys(&mut level, rng, level_index, &marks);
+    // A checkpoint on one of the ledges
+    place_checkpoint(&mut level, rng, level_index, &marks, &held, |y| y == h1 - 1 || y == h2 - 1);
+
+    (level, (params.spawn_x, params.spawn_y))
+}
+
+/// Digs a passage to `to` from whichever cell reachable from `from` is closest,
+/// so the search can follow it: a staircase of single blocks going up, a floor
+/// laid along the way across, and a shaft dropping straight onto `to` going down.
+/// Rock is only ever dug out of or filled into empty space, so nothing already
+/// placed is disturbed, and the ground under the start, trophy and exit stays put.
+fn carve_passage(level: &mut Level, from: (usize, usize), to: (usize, usize), start: (usize, usize)) {
+    let reached = reachable_cells(level, from, &[]);
+    let mut origin = from;
+    for y in 1..level.height() - 1 {
+        for x in 1..level.width() - 1 {
+            let distance = |(cx, cy): (usize, usize)| cx.abs_diff(to.0) + 2 * cy.abs_diff(to.1);
+            if reached[y][x] && level[y + 1][x].is_standable() && distance((x, y)) < distance(origin) {
+                origin = (x, y);
+            }
+        }
+    }
+
+    let dig = |level: &mut Level, x: usize, y: usize| {
+        let holds_up = |tile: Tile| matches!(tile, Tile::Trophy | Tile::Exit);
+        if y > 0 && level[y][x] == Tile::Wall && !holds_up(level[y - 1][x]) && (x, y - 1) != start {
+            level[y][x] = Tile::Empty;
+        }
+    };
+    let fill = |level: &mut Level, x: usize, y: usize| {
+        if level[y][x] == Tile::Empty && (x, y) != start {
+            level[y][x] = Tile::Wall;
+        }
+    };
+
+    let (mut x, mut y) = origin;
+    dig(level, x, y - 1);
+    while (x, y) != to {
+        // Head for the target column; once there, step to whichever side has more room
+        let next_x = match to.0.cmp(&x) {
+            std::cmp::Ordering::Greater => x + 1,
+            std::cmp::Ordering::Less => x - 1,
+            std::cmp::Ordering::Equal if x < level.width() / 2 => x + 1,
+            std::cmp::Ordering::Equal => x - 1,
+        };
+        if y > to.1 {
+            // One stair up, with headroom to jump onto it
+            fill(level, next_x, y);
+            dig(level, next_x, y - 1);
+            dig(level, next_x, y - 2);
+            x = next_x;
+            y -= 1;
+        } else if y < to.1 && x == to.0 {
+            dig(level, x, y + 1);
+            y += 1;
+        } else {
+            dig(level, next_x, y);
+            dig(level, next_x, y - 1);
+            fill(level, next_x, y + 1);
+            x = next_x;
+        }
+    }
+}
+
+/// Empty tiles resting on a wall, within the given columns and rows.
+fn floor_spots(level: &Level, xs: std::ops::Range<usize>, ys: std::ops::RangeInclusive<usize>) -> Vec<(usize, usize)> {
+    ys.flat_map(|y| xs.clone().map(move |x| (x, y)))
+        .filter(|&(x, y)| level[y][x] == Tile::Empty && level[y + 1][x] == Tile::Wall)
+        .collect()
+}
+
+/// Scatters diamonds over the floors of a level with no fixed tiers.
+fn place_diamonds(level: &mut Level, rng: &mut SimpleRng, params: &GenParams, marks: &Landmarks) {
+    let floors = floor_spots(level, 1..level.width() - 1, 1..=level.height() - 2);
+    for _ in 0..params.diamond_attempts {
+        let (dx, dy) = floors[rng.range(0, floors.len() as u32) as usize];
+        if level[dy][dx] == Tile::Empty && (dx, dy) != marks.start {
+            level[dy][dx] = Tile::Diamond;
+        }
+    }
+}
+
+/// Puts hazards on flat stretches of floor, away from the start, trophy and exit and
+/// from any spot `avoid` rules out, following the usual rules on each row: runs of
+/// one or two, at least three tiles apart, and no more than four in any fifteen. If a
+/// row's hazards cut the only way through, they're taken back out, last first, until
+/// it opens up again.
+fn place_floor_hazards(level: &mut Level, rng: &mut SimpleRng, level_index: u32, params: &GenParams, marks: &Landmarks, avoid: impl Fn(usize, usize) -> bool) {
+    let (width, height) = (level.width(), level.height());
+    let solvable = |level: &Level| {
+        is_reachable(level, marks.start, marks.trophy, &[]) && is_reachable(level, marks.trophy, marks.exit, &[])
+    };
+    let floor_chance = if level_index == 1 { params.first_level_floor_hazard_chance } else { params.floor_hazard_chance };
+    // Dave gets a little more room to find his feet at the start
+    let near = |(x, y): (usize, usize), (mx, my): (usize, usize), reach: usize| x.abs_diff(mx) <= reach && y.abs_diff(my) <= 1;
+    for y in 2..height - 1 {
+        let chance = if y == height - 2 { floor_chance } else { params.platform_hazard_chance };
+        let mut last_hazard_end: i32 = -10;
+        let mut runs = Vec::new();
+        for x in 2..width - 2 {
+            let check_valid = |level: &Level, cx: usize| {
+                cx < width - 2 &&
+                level[y][cx] == Tile::Empty &&
+                level[y + 1][cx - 1..=cx + 1].iter().all(|&t| t == Tile::Wall) &&
+                !near((cx, y), marks.start, 4) &&
+                !near((cx, y), marks.trophy, 2) &&
+                !near((cx, y), marks.exit, 2) &&
+                !avoid(cx, y)
+            };
+            if !check_valid(level, x) || (x as i32) - last_hazard_end < 4 {
+                continue;
+            }
+            if rng.range(0, 100) >= chance {
+                continue;
+            }
+
+            let size = if rng.range(0, 2) == 0 { 1 } else { 2 };
+            let run: Vec<usize> = (x..x + size).take_while(|&cx| check_valid(level, cx)).collect();
+            let violation = ((x + run.len()).saturating_sub(15)..=x).any(|window_start| {
+                (window_start..(window_start + 15).min(width))
+                    .filter(|&cx| run.contains(&cx) || level[y][cx].is_hazardous())
+                    .count() > 4
+            });
+            if violation {
+                continue;
+            }
+
+            let hazard = if level_index >= 4 && rng.range(0, 3) == 0 {
+                Tile::FireJet { active: false }
+            } else {
+                Tile::Hazard
+            };
+            for &cx in &run {
+                level[y][cx] = hazard;
+            }
+            last_hazard_end = (x + run.len() - 1) as i32;
+            runs.push(run);
+        }
+
+        if !runs.is_empty() {
+            while !solvable(level) {
+                let Some(run) = runs.pop() else { break };
+                for cx in run {
+                    level[y][cx] = Tile::Empty;
+                }
+            }
+        }
+    }
+}
+
+/// Where the fixed points of a generated level ended up, for the placement passes
+/// shared by every archetype.
+struct Landmarks {
+    start: (usize, usize),
+    trophy: (usize, usize),
+    exit: (usize, usize),
+}
+
+/// Adds teleporter pairs from level 6 (two pairs from level 12), kept clear of the
+/// start, and of the trophy and exit so locking them later can't break a pair up.
+fn place_teleporters(level: &mut Level, rng: &mut SimpleRng, level_index: u32, marks: &Landmarks) {
+    let pair_count = if level_index >= 12 { 2 } else if level_index >= 6 { 1 } else { 0 };
+    for id in 0..pair_count {
+        let near = |(x, y): (usize, usize), (cx, cy): (usize, usize), dx: usize| x.abs_diff(cx) <= dx && y.abs_diff(cy) <= 1;
+        let mut spots: Vec<(usize, usize)> = open_spots(level)
+            .into_iter()
+            .filter(|&pos| {
+                !near(pos, marks.start, 2) &&
+                !near(pos, marks.trophy, 1) &&
+                !near(pos, marks.exit, 1)
+            })
+            .collect();
+        if spots.len() < 2 {
+            break;
+        }
+        // One end low in the level, the other higher up
+        spots.sort_by_key(|&(_, y)| std::cmp::Reverse(y));
+        let half = spots.len() / 2;
+        let low = spots[rng.range(0, half as u32) as usize];
+        let high = spots[rng.range(half as u32, spots.len() as u32) as usize];
+        level[low.1][low.0] = Tile::Teleporter(id);
+        level[high.1][high.0] = Tile::Teleporter(id);
+    }
+}
+
+/// Keys and locked doors: from level 3 the exit sits behind a colored door, and
+/// from level 6 the trophy may be locked away too. Keys are placed in color order,
+/// each reachable from the previous one, so the level can be solved in sequence.
+/// Returns the colors of the keys placed.
+fn place_keys(level: &mut Level, rng: &mut SimpleRng, level_index: u32, marks: &Landmarks) -> Vec<KeyColor> {
+    let (trophy_x, trophy_y) = marks.trophy;
+    let mut locks = Vec::new();
+    if level_index >= 3 {
+        locks.push(marks.exit);
+    }
+    if level_index >= 6 && level[trophy_y + 1][trophy_x] == Tile::Wall && rng.range(0, 2) == 0 {
+        locks.push(marks.trophy);
+    }
+    let mut colors = KeyColor::ALL.to_vec();
+    let mut locks: Vec<(KeyColor, SavedTiles)> = locks
+        .into_iter()
+        .filter_map(|(lx, ly)| {
+            let color = colors.remove(rng.range(0, colors.len() as u32) as usize);
+            let saved = lock_tile(level, lx, ly, color);
+            // The wall capping the lock can seal off a spot only reachable from above
+            if is_reachable(level, marks.start, marks.trophy, &KeyColor::ALL) &&
+               is_reachable(level, marks.trophy, marks.exit, &KeyColor::ALL) {
+                Some((color, saved))
+            } else {
+                for ((sx, sy), tile) in saved {
+                    level[sy][sx] = tile;
+                }
+                None
+            }
+        })
+        .collect();
+    locks.sort_by_key(|lock| lock.0);
+
+    let mut from = marks.start;
+    let mut held = Vec::new();
+    let mut placed = Vec::new();
+    let lock_count = locks.len();
+    for (i, (color, saved)) in locks.into_iter().enumerate() {
+        let mut with_key = held.clone();
+        with_key.push(color);
+        let mut candidates = open_spots(level);
+
+        let mut key_pos = None;
+        for _ in 0..20 {
+            if candidates.is_empty() {
+                break;
+            }
+            let pos = candidates.swap_remove(rng.range(0, candidates.len() as u32) as usize);
+            // The trophy only has to be reachable once the last key is in hand
+            if is_reachable(level, from, pos, &held) &&
+               (i + 1 < lock_count || is_reachable(level, pos, marks.trophy, &with_key)) {
+                key_pos = Some(pos);
+                break;
+            }
+        }
+
+        match key_pos {
+            Some((kx, ky)) => {
+                level[ky][kx] = Tile::Key(color);
+                held = with_key;
+                from = (kx, ky);
+                placed.push(((kx, ky), saved));
+            }
+            None => {
+                // No safe spot for the key: drop the lock instead of making the level unsolvable
+                for ((sx, sy), tile) in saved {
+                    level[sy][sx] = tile;
+                }
+            }
+        }
+    }
+
+    // Dropping the last lock skips the check that the trophy can be reached from the
+    // key before it; if it can't, take every lock back out instead
+    if !is_reachable(level, from, marks.trophy, &held) {
+        for ((kx, ky), saved) in placed {
+            level[ky][kx] = Tile::Empty;
+            for ((sx, sy), tile) in saved {
+                level[sy][sx] = tile;
+            }
+        }
+        held.clear();
+    }
+    held
+}
+
+/// Adds a checkpoint from level 2 on a row `row` accepts, reachable from the start
+/// with the keys in `held`.
+fn place_checkpoint(level: &mut Level, rng: &mut SimpleRng, level_index: u32, marks: &Landmarks, held: &[KeyColor], row: impl Fn(usize) -> bool) {
+    if level_index < 2 {
+        return;
+    }
+    let mut spots: Vec<(usize, usize)> = open_spots(level)
+        .into_iter()
+        .filter(|&(_, y)| row(y))
+        .collect();
+    for _ in 0..10 {
+        if spots.is_empty() {
+            break;
+        }
+        let pos = spots.swap_remove(rng.range(0, spots.len() as u32) as usize);
+        if is_reachable(level, marks.start, pos, held) {
+            level[pos.1][pos.0] = Tile::Checkpoint;
+            break;
+        }
+    }
+}
+
+/// Returns every empty tile Dave could stand in that has no hazard right next to it,
+/// used for placing keys and teleporters.
+fn open_spots(level: &Level) -> Vec<(usize, usize)> {
+    let mut spots = Vec::new();
+    for y in 1..level.height() - 1 {
+        for x in 1..level.width() - 1 {
+            if level[y][x] == Tile::Empty && level[y + 1][x].is_standable() &&
+               !level[y][x - 1].is_hazardous() && !level[y][x + 1].is_hazardous() {
+                spots.push((x, y));
+            }
+        }
+    }
+    spots
+}
+
+/// Finds the other end of the teleporter at `pos`, if it has one.
+pub fn teleporter_partner(level: &Level, pos: (usize, usize)) -> Option<(usize, usize)> {
+    let Tile::Teleporter(id) = level[pos.1][pos.0] else {
+        return None;
+    };
+    for y in 0..level.height() {
+        for x in 0..level.width() {
+            if (x, y) != pos && level[y][x] == Tile::Teleporter(id) {
+                return Some((x, y));
+            }
+        }
+    }
+    None
+}
+
+/// Tiles overwritten while generating, with their positions, so they can be restored.
+type SavedTiles = Vec<((usize, usize), Tile)>;
+
+/// Encloses the tile at (x, y) with doors of the given color on both sides and a
+/// wall above it. Returns the overwritten tiles so the lock can be undone.
+fn lock_tile(level: &mut Level, x: usize, y: usize, color: KeyColor) -> SavedTiles {
+    let mut saved = Vec::new();
+    for (cx, cy, tile) in [(x - 1, y, Tile::Door(color)), (x + 1, y, Tile::Door(color)), (x, y - 1, Tile::Wall)] {
+        if level[cy][cx] != Tile::Wall {
+            saved.push(((cx, cy), level[cy][cx]));
+            level[cy][cx] = tile;
+        }
+    }
+    saved
+}
+
+/// A simple BFS to check reachability in the level.
+/// Accounts for horizontal movement, falling, and jumping. Doors are solid unless
+/// their color is in `keys`; fire jets are treated as passable since Dave can time
+/// his way past them, and crumbling floors as solid since they always respawn.
+/// Springs, conveyors and ice count as ordinary ground, so levels never depend on them.
+/// Teleporter pairs are an extra edge between their two ends.
+pub fn is_reachable(level: &Level, start: (usize, usize), target: (usize, usize), keys: &[KeyColor]) -> bool {
+    let mut visited = vec![vec![false; level.width()]; level.height()];
+    let mut queue = VecDeque::new();
+
+    queue.push_back(start);
+    visited[start.1][start.0] = true;
+
+    while let Some(pos) = queue.pop_front() {
+        if pos == target {
+            return true;
+        }
+        for (nx, ny) in moves(level, pos, keys) {
+            if !visited[ny][nx] {
+                visited[ny][nx] = true;
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+
+    false
+}
+
+/// Every cell reachable from `start` with the keys in `keys`, by the same moves as
+/// `is_reachable`.
+fn reachable_cells(level: &Level, start: (usize, usize), keys: &[KeyColor]) -> Vec<Vec<bool>> {
+    let mut visited = vec![vec![false; level.width()]; level.height()];
+    let mut queue = VecDeque::new();
+
+    queue.push_back(start);
+    visited[start.1][start.0] = true;
+
+    while let Some(pos) = queue.pop_front() {
+        for (nx, ny) in moves(level, pos, keys) {
+            if !visited[ny][nx] {
+                visited[ny][nx] = true;
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+
+    visited
+}
+
+/// The cells Dave can get to in one move from (cx, cy), for the reachability searches.
+fn moves(level: &Level, (cx, cy): (usize, usize), keys: &[KeyColor]) -> Vec<(usize, usize)> {
+    let (width, height) = (level.width(), level.height());
+    let is_solid = |tile: Tile| match tile {
+        Tile::Door(color) => !keys.contains(&color),
+        Tile::Platform => false,
+        tile => tile.is_standable(),
+    };
+
+    // Potential next positions
+    let mut neighbors = Vec::new();
+
+    let is_safe = |nx: usize, ny: usize| {
+        nx < width && ny < height && 
+        !is_solid(level[ny][nx]) &&
+        level[ny][nx] != Tile::Hazard
+    };
+    // Cells boxed in on all four sides (like a locked alcove) can't be jumped
+    // or fallen into diagonally.
+    let is_enterable = |nx: usize, ny: usize| {
+        (nx > 0 && !is_solid(level[ny][nx - 1])) ||
+        (nx + 1 < width && !is_solid(level[ny][nx + 1])) ||
+        (ny > 0 && !is_solid(level[ny - 1][nx])) ||
+        (ny + 1 < height && !is_solid(level[ny + 1][nx]))
+    };
+
+    let on_ground = cy + 1 < height && (level[cy + 1][cx].is_standable() || is_solid(level[cy + 1][cx]));
+
+    // 0. Teleport to the partner
+    if let Some(partner) = teleporter_partner(level, (cx, cy)) {
+        neighbors.push(partner);
+    }
+
+    // 1. Walk left/right
+    if cx > 0 && is_safe(cx - 1, cy) {
+        neighbors.push((cx - 1, cy));
+    }
+    if cx + 1 < width && is_safe(cx + 1, cy) {
+        neighbors.push((cx + 1, cy));
+    }
+
+    // 2. Fall down (or drop through a one-way platform)
+    if on_ground && level[cy + 1][cx] == Tile::Platform && is_safe(cx, cy + 1) {
+        neighbors.push((cx, cy + 1));
+    }
+    if !on_ground {
+        if cy + 1 < height && is_safe(cx, cy + 1) {
+            neighbors.push((cx, cy + 1));
+        }
+        // Optional: air control / diagonal falling
+        if cx > 0 && cy + 1 < height && is_safe(cx - 1, cy + 1) && is_enterable(cx - 1, cy + 1) {
+            neighbors.push((cx - 1, cy + 1));
+        }
+        if cx + 1 < width && cy + 1 < height && is_safe(cx + 1, cy + 1) && is_enterable(cx + 1, cy + 1) {
+            neighbors.push((cx + 1, cy + 1));
+        }
+    }
+
+    // 3. Jump (if on ground)
+    if on_ground {
+        // Dave can jump ~4 tiles high and ~20 tiles horizontally.
+        // We'll use a slightly conservative box to simulate reachable area.
+        for dy in 1..=4 {
+            if cy >= dy {
+                let ny = cy - dy;
+                // Horizontal range depends on height
+                // At peak (dy=4), horizontal offset can be ~10
+                // We'll just allow a generous range and assume Dave can make the arc.
+                let h_range = match dy {
+                    1 => 5,
+                    2 => 8,
+                    3 => 10,
+                    4 => 12,
+                    _ => 0,
+                };
+                for dx in -h_range..=h_range {
+                    let nx = cx as i32 + dx;
+                    if nx >= 0 && nx < width as i32 {
+                        let nx = nx as usize;
+                        if is_safe(nx, ny) && is_enterable(nx, ny) {
+                            neighbors.push((nx, ny));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    neighbors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_reachable_respects_doors() {
+        let mut level = Level::new(LEVEL_WIDTH, LEVEL_HEIGHT);
+        for x in 0..LEVEL_WIDTH {
+            level[LEVEL_HEIGHT - 1][x] = Tile::Wall;
+        }
+        // Target sits in an alcove walled in on every side but a green door
+        level[LEVEL_HEIGHT - 3][50] = Tile::Wall;
+        level[LEVEL_HEIGHT - 2][51] = Tile::Wall;
+        level[LEVEL_HEIGHT - 2][49] = Tile::Door(KeyColor::Green);
+
+        let start = (2, LEVEL_HEIGHT - 2);
+        let target = (50, LEVEL_HEIGHT - 2);
+        assert!(!is_reachable(&level, start, target, &[]));
+        assert!(!is_reachable(&level, start, target, &[KeyColor::Red]));
+        assert!(is_reachable(&level, start, target, &[KeyColor::Green]));
+    }
+
+    #[test]
+    fn test_generated_keys_come_before_their_doors() {
+        for level_num in 3..40 {
+            let (level, (px, py)) = generate_level(level_num);
+            let mut keys = Vec::new();
+            for y in 0..level.height() {
+                for x in 0..level.width() {
+                    if let Tile::Key(color) = level[y][x] {
+                        keys.push((color, (x, y)));
+                    }
+                }
+            }
+            assert!(!keys.is_empty(), "level {} has no keys", level_num);
+            keys.sort();
+
+            let mut from = (px as usize, py as usize);
+            let mut held = Vec::new();
+            for (color, pos) in keys {
+                assert!(is_reachable(&level, from, pos, &held), "level {}: {:?} key unreachable", level_num, color);
+                held.push(color);
+                from = pos;
+            }
+        }
+    }
+
+    #[test]
+    fn test_difficulty_curve_stays_solvable() {
+        let params = GenParams::default();
+        let curve = &params.difficulty;
+        assert_eq!(params.at_level(1), params);
+        let hardest = params.at_level(curve.ramp_levels);
+        assert_eq!(hardest.floor_hazard_chance, curve.floor_hazard_chance);
+        assert_eq!(hardest.island_length, curve.island_length);
+        assert_eq!(hardest.diamond_attempts, curve.diamond_attempts);
+        assert_eq!(params.at_level(curve.ramp_levels * 10), hardest);
+    }
+
+    #[test]
+    fn test_every_archetype_stays_solvable() {
+        for archetype in Archetype::ALL {
+            let params = GenParams { archetypes: vec![archetype], ..GenParams::default() };
+            let mut built = Vec::new();
+            for seed in 0..5 {
+                for level_index in 1..=params.difficulty.ramp_levels {
+                    let (level, (px, py), kind) = generate_level_and_archetype(seed, level_index, &params);
+                    let label = format!("{:?} seed {} level {}", archetype, seed, level_index);
+                    assert_solvable(&level, (px as usize, py as usize), &label);
+                    built.push(kind);
+                }
+            }
+            // Only the odd cave that carving can't join up falls back to a zig-zag
+            let count = built.iter().filter(|&&kind| kind == archetype).count();
+            let needed = if archetype == Archetype::Cave { built.len() * 9 / 10 } else { built.len() };
+            assert!(count >= needed, "only {} of {} levels were built as {:?}", count, built.len(), archetype);
+        }
+    }
+
+    /// Checks that the keys can be collected in color order, then the trophy, then the exit.
+    fn assert_solvable(level: &Level, start: (usize, usize), label: &str) {
+        let find = |wanted: Tile| (0..level.height())
+            .flat_map(|y| (0..level.width()).map(move |x| (x, y)))
+            .find(|&(x, y)| level[y][x] == wanted)
+            .expect("every level has a trophy and an exit");
+        let mut keys: Vec<(KeyColor, (usize, usize))> = KeyColor::ALL
+            .iter()
+            .filter_map(|&color| (0..level.height())
+                .flat_map(|y| (0..level.width()).map(move |x| (x, y)))
+                .find(|&(x, y)| level[y][x] == Tile::Key(color))
+                .map(|pos| (color, pos)))
+            .collect();
+        keys.sort();
+
+        let mut from = start;
+        let mut held = Vec::new();
+        for (color, pos) in keys {
+            assert!(is_reachable(level, from, pos, &held), "{}: {:?} key unreachable", label, color);
+            held.push(color);
+            from = pos;
+        }
+        let trophy = find(Tile::Trophy);
+        assert!(is_reachable(level, from, trophy, &held), "{}: trophy unreachable", label);
+        assert!(is_reachable(level, trophy, find(Tile::Exit), &held), "{}: exit unreachable", label);
+    }
+
+    #[test]
+    fn test_caves_carve_through_solid_rock() {
+        let solid = GenParams { archetypes: vec![Archetype::Cave], cave_fill: 100, ..GenParams::default() };
+        let (level, (px, py)) = generate_level_with(3, 10, &solid);
+        assert_solvable(&level, (px as usize, py as usize), "solid rock");
+    }
+
+    #[test]
+    fn test_zigzag_tiers_jump_up_through_platforms() {
+        let params = GenParams { archetypes: vec![Archetype::ZigZag], ..GenParams::default() };
+        for seed in 0..5 {
+            let (level, _) = generate_level_with(seed, 1, &params);
+            for &h in &params.tier_heights[1..] {
+                assert!(level[h].contains(&Tile::Platform), "seed {} tier at row {}", seed, h);
+            }
+        }
+    }
+
+    #[test]
+    fn test_level_size() {
+        let level = Level::new(LEVEL_WIDTH, 3);
+        assert_eq!((level.width(), level.height()), (LEVEL_WIDTH, 3));
+        let level = Level::new(0, 3);
+        assert_eq!((level.width(), level.height()), (0, 0));
+    }
+
+    #[test]
+    fn test_towers_climb_to_the_exit() {
+        let params = GenParams { archetypes: vec![Archetype::Tower], tower_screens: 2, ..GenParams::default() };
+        for seed in 0..3 {
+            for level_index in 1..=10 {
+                let (level, (_, py)) = generate_level_with(seed, level_index, &params);
+                assert_eq!((level.width(), level.height()), (LEVEL_WIDTH, LEVEL_HEIGHT * 2));
+                assert!(py as usize >= LEVEL_HEIGHT, "Dave starts in the bottom screen");
+                let exit_row = (0..level.height()).find(|&y| level[y].contains(&Tile::Exit));
+                assert!(exit_row.is_some_and(|y| y < LEVEL_HEIGHT / 2), "the exit is at the top");
+            }
+        }
+    }
+
+    #[test]
+    fn test_pits_open_through_the_floor() {
+        let params = GenParams { archetypes: vec![Archetype::Pit], ..GenParams::default() };
+        for seed in 0..5 {
+            for level_index in 1..=params.difficulty.ramp_levels {
+                let (level, _) = generate_level_with(seed, level_index, &params);
+                let label = format!("seed {} level {}", seed, level_index);
+                let floor = &level[LEVEL_HEIGHT - 1];
+                assert!(floor.contains(&Tile::Empty), "{}: no pits", label);
+                assert!(floor.split(|&t| t != Tile::Empty).all(|pit| pit.len() <= MAX_PIT_WIDTH), "{}: pit too wide", label);
+            }
+        }
+    }
+
+    #[test]
+    fn test_seed_changes_layout() {
+        let params = GenParams::default();
+        assert_eq!(generate_level_with(0, 5, &params), generate_level(5));
+        assert_eq!(generate_level_with(42, 5, &params), generate_level_with(42, 5, &params));
+        assert_ne!(generate_level_with(42, 5, &params).0, generate_level(5).0);
+        // The same seed still gives each level its own layout
+        assert_ne!(generate_level_with(42, 5, &params).0, generate_level_with(42, 6, &params).0);
+
+        let zigzag = GenParams { archetypes: vec![Archetype::ZigZag], ..GenParams::default() };
+        for seed in 0..10 {
+            let (level, _) = generate_level_with(seed, 4, &zigzag);
+            // The zig-zag's top tier always reaches the right wall
+            assert!(level[4][58].is_standable(), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn test_merge_into_preserves_unrelated_entries() {
+        let existing = "# my settings\nmax_level = 20\nfuture_option = true\n\n[physics]\ngravity = 70.0 # floaty\n\n[spring]\nvy = -50.0\n";
+        let mut config = Config { max_level: 42, preset: Preset::Hard, ..Config::default() };
+        config.physics.gravity = 90.0;
+        config.keys.jump = vec!["Space".to_string()];
+
+        let merged = config.merge_into(existing);
+        assert!(merged.starts_with("# my settings"));
+        assert!(merged.contains("future_option = true"));
+        assert!(merged.contains("gravity = 90.0 # floaty"));
+        assert!(merged.contains("[spring]\nvy = -50.0"));
+
+        let reloaded: Config = toml::from_str(&merged).unwrap();
+        assert_eq!(reloaded.max_level, 42);
+        assert_eq!(reloaded.preset, Preset::Hard);
+        assert_eq!(reloaded.keys.jump, vec!["Space".to_string()]);
+        assert_eq!(reloaded.spring.vy, -50.0);
+    }
+
+    #[test]
+    fn test_key_binding_names_round_trip() {
+        use crossterm::event::KeyCode;
+        for code in [KeyCode::Left, KeyCode::Enter, KeyCode::Char(' '), KeyCode::Char('x')] {
+            let key = KeyBinding::from(code);
+            assert_eq!(KeyBinding::parse(&key.name().unwrap()), Some(key));
+        }
+        assert_eq!(KeyBinding::parse("X"), Some(KeyBinding::from(KeyCode::Char('x'))));
+        assert_eq!(KeyBinding::from(KeyCode::Null).name(), None);
+        assert_eq!(KeyBinding::parse("Lft"), None);
+    }
+
+    #[test]
+    fn test_key_binding_rich_syntax() {
+        use crossterm::event::{KeyCode, KeyModifiers};
+        let ctrl_r = KeyBinding::parse("Ctrl+R").unwrap();
+        assert_eq!(ctrl_r, KeyBinding::new(KeyCode::Char('r'), KeyModifiers::CONTROL));
+        assert_ne!(ctrl_r, KeyBinding::parse("r").unwrap());
+        assert_eq!(KeyBinding::parse("Shift+Tab"), KeyBinding::parse("BackTab"));
+        assert_eq!(KeyBinding::parse("F5").unwrap().code, KeyCode::F(5));
+        assert_eq!(KeyBinding::parse("F25"), None);
+        assert!(KeyBinding::parse("Num+").unwrap().keypad);
+        assert_eq!(KeyBinding::parse("Alt+Foo"), None);
+        assert_eq!(KeyBinding::parse("Hyper+a"), None);
+        for name in ["Ctrl+r", "Alt+Shift+PageUp", "Num8", "NumEnter", "Ctrl++", "Home", "Backspace", "Shift+Tab", "F12"] {
+            let key = KeyBinding::parse(name).unwrap();
+            assert_eq!(key.name().as_deref(), Some(name));
+        }
+
+        // Number pad keys fall back to their ordinary counterparts
+        let keys = KeysConfig { jump: vec!["8".to_string()], ..KeysConfig::default() };
+        let actions = ActionMap::new(&keys);
+        let num8 = KeyBinding::parse("Num8").unwrap();
+        assert_eq!(actions.actions(num8), &[Action::Jump]);
+    }
+
+    #[test]
+    fn test_conflicting_bindings_are_reported() {
+        let (_, issues) = Config::parse("[keys]\njump = [\"Space\", \"Up\"]\nquit = [\"Esc\", \"space\"]\n");
+        assert_eq!(issues, vec![ConfigIssue::Warning("keys: `Space` is bound to both jump and quit".to_string())]);
+    }
+
+    #[test]
+    fn test_action_map_lookup() {
+        use crossterm::event::{KeyCode, KeyModifiers};
+        let actions = ActionMap::new(&KeysConfig::default());
+        assert_eq!(actions.actions(KeyBinding::from(KeyCode::Left)), &[Action::Left]);
+        // Shifted letters match their lowercase binding
+        assert_eq!(actions.actions(KeyBinding::new(KeyCode::Char('A'), KeyModifiers::SHIFT)), &[Action::Left]);
+        // Unbound chords fall back to the key without modifiers
+        assert_eq!(actions.actions(KeyBinding::new(KeyCode::Right, KeyModifiers::SHIFT)), &[Action::Right]);
+        assert_eq!(actions.actions(KeyBinding::new(KeyCode::Left, KeyModifiers::CONTROL)), &[Action::Left]);
+        assert!(actions.actions(KeyBinding::new(KeyCode::F(7), KeyModifiers::SHIFT)).is_empty());
+        let keys = KeysConfig { jump: vec!["Shift+Up".to_string()], ..KeysConfig::default() };
+        let chorded = ActionMap::new(&keys);
+        assert_eq!(chorded.actions(KeyBinding::new(KeyCode::Up, KeyModifiers::SHIFT)), &[Action::Jump]);
+
+        let held = [KeyBinding::from(KeyCode::Char('d')), KeyBinding::from(KeyCode::Char(' '))];
+        assert_eq!(actions.held(&held), [Action::Right, Action::Jump].into_iter().collect());
+    }
+
+    #[test]
+    fn test_parse_reports_syntax_error_location() {
+        let (config, issues) = Config::parse("max_level = 12\n[physics]\ngravity = = 3\n");
+        assert_eq!(config.max_level, Config::default().max_level);
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].is_error());
+        assert!(issues[0].to_string().contains("config.toml:3:"), "{}", issues[0]);
+    }
+
+    #[test]
+    fn test_parse_warns_and_clamps() {
+        let text = toml::to_string(&Config::default())
+            .unwrap()
+            .replace("max_level = 10\n", "max_level = 12\nmax_levle = 3\n")
+            .replace("jump_vy = -28.0", "jump_vy = 28.0")
+            .replace("gravity = 80.0", "gravity = 90.0")
+            .replace("left = [\"Left\"", "left = [\"Lft\"");
+        let (config, issues) = Config::parse(&text);
+        assert_eq!(config.max_level, 12);
+        assert_eq!(config.physics.gravity, 90.0);
+        assert_eq!(config.physics.jump_vy, Config::default().physics.jump_vy);
+
+        let messages: Vec<String> = issues.iter().map(ToString::to_string).collect();
+        assert_eq!(messages.len(), 3, "{:?}", messages);
+        assert!(messages.iter().any(|m| m.starts_with("warning: config.toml:2:1: unknown setting `max_levle`")), "{:?}", messages);
+        assert!(messages.iter().any(|m| m.contains("keys.left: unrecognized key name `Lft`")), "{:?}", messages);
+        assert!(messages.iter().any(|m| m.starts_with("error:") && m.contains("physics.jump_vy")), "{:?}", messages);
+    }
+
+    #[test]
+    fn test_generator_section() {
+        let text = "[generator]\narchetypes = [\"islands\"]\ntier_heights = [15, 11, 7, 3]\nfloor_hazard_chance = 150\n";
+        let (config, issues) = Config::parse(text);
+        assert_eq!(config.generator.archetypes, vec![Archetype::Islands]);
+        assert_eq!(config.generator.tier_heights, [15, 11, 7, 3]);
+        assert_eq!(config.generator.floor_hazard_chance, GenParams::default().floor_hazard_chance);
+        assert_eq!(issues.len(), 1, "{:?}", issues);
+        assert!(issues[0].to_string().contains("generator.floor_hazard_chance"));
+
+        // The trophy sits on the top tier wherever that is
+        let (level, _) = generate_level_with(7, 3, &config.generator);
+        assert!((0..LEVEL_WIDTH).any(|x| level[2][x] == Tile::Trophy));
+
+        let (config, issues) = Config::parse("[generator]\ntier_heights = [16, 10, 8, 4]\narchetypes = []\ncave_smoothing = 11\ntower_screens = 1\n");
+        assert_eq!(config.generator, GenParams::default());
+        assert_eq!(issues.len(), 4, "{:?}", issues);
+
+        // Dave can't start inside a ledge or in mid-air
+        let (config, issues) = Config::parse("[generator]\nspawn_x = 30.0\nspawn_y = 5.5\n");
+        assert_eq!((config.generator.spawn_x, config.generator.spawn_y), (2.0, 17.99));
+        assert_eq!(issues.len(), 2, "{:?}", issues);
+        assert!(issues[1].to_string().contains("on the base platform"));
+
+        // Every problem resets its own setting
+        let mut broken = GenParams {
+            archetypes: Vec::new(),
+            tier_heights: [18, 18, 18, 18],
+            first_level_floor_hazard_chance: 101,
+            floor_hazard_chance: 101,
+            platform_hazard_chance: 101,
+            island_length: [1, 1],
+            cave_fill: 101,
+            cave_smoothing: 11,
+            tower_screens: 6,
+            spawn_x: 0.0,
+            spawn_y: 0.0,
+            difficulty: DifficultyCurve {
+                ramp_levels: 0,
+                floor_hazard_chance: 101,
+                platform_hazard_chance: 101,
+                island_length: [1, 1],
+                ledge_shrink: 7,
+                ..DifficultyCurve::default()
+            },
+            ..GenParams::default()
+        };
+        let problems = broken.problems();
+        assert_eq!(problems.len(), 16);
+        for problem in &problems {
+            problem.reset(&mut broken);
+        }
+        assert_eq!(broken, GenParams::default());
+    }
+
+    #[test]
+    fn test_config_paths_discover_order() {
+        let vars = |name: &str| match name {
+            "XDG_CONFIG_DIRS" => Some("/etc/a:/etc/b".to_string()),
+            "HOME" => Some("/home/dave".to_string()),
+            "RUSTYDAVE_CONFIG" => Some("/tmp/env.toml".to_string()),
+            _ => None,
+        };
+        let paths = ConfigPaths::discover(Some(Path::new("cli.toml")), vars);
+        let layers: Vec<(String, bool)> = paths.layers.iter().map(|l| (l.path.display().to_string(), l.required)).collect();
+        assert_eq!(layers, vec![
+            ("/etc/b/rustydave/config.toml".to_string(), false),
+            ("/etc/a/rustydave/config.toml".to_string(), false),
+            ("/home/dave/.config/rustydave/config.toml".to_string(), false),
+            ("config.toml".to_string(), false),
+            ("/tmp/env.toml".to_string(), true),
+            ("cli.toml".to_string(), true),
+        ]);
+        assert_eq!(paths.save_target(), PathBuf::from("cli.toml"));
+    }
+
+    #[test]
+    fn test_from_sources_layers_override() {
+        let sources = [
+            ("user".to_string(), "max_level = 50\n[physics]\ngravity = 70.0\n".to_string()),
+            ("broken".to_string(), "max_level = \"lots\"\n".to_string()),
+            ("project".to_string(), "[physics]\nfriction = 300.0\n".to_string()),
+        ];
+        let (config, issues) = Config::from_sources(&sources);
+        assert_eq!(config.max_level, 50);
+        assert_eq!(config.physics.gravity, 70.0);
+        assert_eq!(config.physics.friction, 300.0);
+        assert_eq!(config.physics.jump_vy, PhysicsConfig::default().jump_vy);
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].to_string().starts_with("error: broken:1:13:"), "{}", issues[0]);
+    }
+
+    #[test]
+    fn test_held_keys_repeat_timing() {
+        use crossterm::event::KeyCode;
+        let right = KeyBinding::from(KeyCode::Right);
+        let mut keys = HeldKeys::new(&InputConfig::default(), false);
+        assert!(!keys.uses_releases());
+
+        // A press lasts through the auto-repeat delay, then each repeat a little longer
+        keys.press(right);
+        keys.tick(0.5);
+        assert_eq!(keys.iter().count(), 1);
+        keys.press(right);
+        keys.tick(0.05);
+        assert_eq!(keys.iter().count(), 1);
+        keys.tick(0.06);
+        assert_eq!(keys.iter().count(), 0);
+
+        // Seeing a release event switches auto mode over to release events
+        keys.press(right);
+        keys.release(KeyBinding::from(KeyCode::Left));
+        assert!(keys.uses_releases());
+        keys.tick(5.0);
+        assert_eq!(keys.iter().count(), 1);
+        keys.release(right);
+        assert_eq!(keys.iter().count(), 0);
+
+        let config = InputConfig { release_mode: ReleaseMode::Repeat, ..InputConfig::default() };
+        let mut keys = HeldKeys::new(&config, true);
+        keys.release(right);
+        assert!(!keys.uses_releases());
+
+        // A reloaded release mode takes effect without a restart
+        keys.configure(&InputConfig::default());
+        assert!(keys.uses_releases());
+        keys.press(right);
+        keys.configure(&config);
+        assert!(!keys.uses_releases());
+        assert_eq!(keys.iter().count(), 0);
+    }
+}
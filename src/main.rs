@@ -1,16 +1,33 @@
 use std::io::{self, Write};
-use std::time::{Duration, Instant};
-use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+use std::collections::{HashMap, HashSet};
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEventKind, KeyboardEnhancementFlags, PushKeyboardEnhancementFlags, PopKeyboardEnhancementFlags},
+    event::{self, DisableFocusChange, EnableFocusChange, Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags, PushKeyboardEnhancementFlags, PopKeyboardEnhancementFlags},
     execute, queue,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen, Clear, ClearType},
+    terminal::{disable_raw_mode, enable_raw_mode, supports_keyboard_enhancement, EnterAlternateScreen, LeaveAlternateScreen, Clear, ClearType},
     cursor,
     style::{Color, Print, SetForegroundColor, ResetColor},
 };
 
-use rustydave::{Tile, LEVEL_WIDTH, LEVEL_HEIGHT, generate_level, Config};
+use rustydave::cli::{Cli, Graphics, Opt};
+use rustydave::{Tile, KeyColor, Level, LEVEL_WIDTH, LEVEL_HEIGHT, generate_level_with, teleporter_partner, Action, ActionMap, HeldKeys, KeyBinding, Config, ConfigIssue, ConfigPaths, Preset, RespawnMode};
 
+/// How long (in seconds) one-way platforms stay passable after a drop-through.
+const DROP_THROUGH_TIME: f32 = 0.2;
+/// Delay (in seconds) between Dave stepping on a crumbling floor and it collapsing.
+const CRUMBLE_DELAY: f32 = 0.5;
+/// Time (in seconds) before a collapsed floor grows back.
+const CRUMBLE_RESPAWN_TIME: f32 = 3.0;
+/// Time (in seconds) a fire jet stays lit.
+const FIRE_JET_ON_TIME: f32 = 1.0;
+/// Time (in seconds) a fire jet stays off between bursts.
+const FIRE_JET_OFF_TIME: f32 = 2.0;
+/// Time (in seconds) Dave can't be hurt after respawning.
+const INVULNERABLE_TIME: f32 = 1.5;
+/// How often (in seconds) the config files are checked for changes.
+const CONFIG_POLL_INTERVAL: f32 = 0.5;
 
 /// Represents the player character, Dave.
 struct Player {
@@ -26,26 +43,170 @@ struct Player {
     on_ground: bool,
     /// Whether Dave has collected the trophy for the current level.
     has_trophy: bool,
+    /// Colored keys Dave has picked up on the current level.
+    inventory: Vec<KeyColor>,
     /// Timer for coyote time (jumping after leaving a platform).
     coyote_timer: f32,
     /// Timer for jump buffering (inputting jump before landing).
     jump_buffer_timer: f32,
+    /// Timer during which one-way platforms are ignored after dropping through one.
+    drop_timer: f32,
+    /// Whether the current upward motion comes from a spring, so releasing jump doesn't cut it short.
+    launched: bool,
+    /// Whether Dave is standing in a teleporter he arrived through, so it doesn't send him straight back.
+    in_teleporter: bool,
+    /// Timer for invulnerability after respawning (hazards are harmless while positive).
+    invuln_timer: f32,
+}
+
+impl Player {
+    /// Stops all movement and clears the movement timers.
+    fn stop(&mut self) {
+        self.vx = 0.0;
+        self.vy = 0.0;
+        self.on_ground = false;
+        self.coyote_timer = 0.0;
+        self.jump_buffer_timer = 0.0;
+        self.drop_timer = 0.0;
+        self.launched = false;
+        self.in_teleporter = false;
+    }
+}
+
+/// The screen the game is on; drives what `update`, `handle_key` and `draw` do.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Screen {
+    /// Title screen shown at startup.
+    Title,
+    /// Normal level play.
+    Playing,
+    /// Dave lost a life and is waiting to respawn.
+    Dead,
+    /// The pause menu.
+    Paused,
+    /// The options screen, opened from the title screen or the pause menu.
+    Options,
+    /// Summary shown after reaching the exit with the trophy.
+    LevelComplete,
+    /// Dave ran out of lives.
+    GameOver,
+    /// All levels are complete.
+    Won,
+    /// Play was interrupted by the terminal losing focus; any key resumes.
+    FocusLost,
+    /// The game loop should stop.
+    Quit,
+}
+
+/// An entry of the title screen or pause menu.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum MenuItem {
+    StartGame,
+    Resume,
+    RestartLevel,
+    Options,
+    Quit,
+}
+
+impl MenuItem {
+    /// Text shown for the entry.
+    fn label(self) -> &'static str {
+        match self {
+            MenuItem::StartGame => "Start Game",
+            MenuItem::Resume => "Resume",
+            MenuItem::RestartLevel => "Restart Level",
+            MenuItem::Options => "Options",
+            MenuItem::Quit => "Quit",
+        }
+    }
+}
+
+/// Entries of the title screen menu.
+const TITLE_MENU: [MenuItem; 3] = [MenuItem::StartGame, MenuItem::Options, MenuItem::Quit];
+/// Entries of the pause menu.
+const PAUSE_MENU: [MenuItem; 4] = [MenuItem::Resume, MenuItem::RestartLevel, MenuItem::Options, MenuItem::Quit];
+
+/// A row of the options screen.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum OptionRow {
+    /// A `[physics]` value and how much Left/Right change it by.
+    Physics(&'static str, f32),
+    /// The highest level number.
+    MaxLevel,
+    /// The difficulty preset; Left/Right cycle through them.
+    Preset,
+    /// A `[keys]` binding; ENTER waits for a key press to rebind it.
+    Binding(Action),
+    /// Write the options to `config.toml`.
+    Save,
+    /// Leave the options screen.
+    Back,
+}
+
+/// Rows of the options screen, in display order.
+const OPTION_ROWS: [OptionRow; 20] = [
+    OptionRow::Physics("target_vx", 1.0),
+    OptionRow::Physics("accel_ground", 10.0),
+    OptionRow::Physics("accel_air", 5.0),
+    OptionRow::Physics("jump_vy", 1.0),
+    OptionRow::Physics("gravity", 5.0),
+    OptionRow::Physics("coyote_time", 0.01),
+    OptionRow::Physics("jump_buffer_time", 0.01),
+    OptionRow::Physics("jump_release_gravity_mult", 0.1),
+    OptionRow::Physics("friction", 10.0),
+    OptionRow::MaxLevel,
+    OptionRow::Preset,
+    OptionRow::Binding(Action::Left),
+    OptionRow::Binding(Action::Right),
+    OptionRow::Binding(Action::Jump),
+    OptionRow::Binding(Action::Down),
+    OptionRow::Binding(Action::Pause),
+    OptionRow::Binding(Action::Quit),
+    OptionRow::Binding(Action::Restart),
+    OptionRow::Save,
+    OptionRow::Back,
+];
+
+/// How many option rows the options box shows at once. With its title, blank line,
+/// hint and borders it then fits in the level area.
+const OPTION_ROWS_SHOWN: usize = LEVEL_HEIGHT + 1 - 5;
+
+/// Snapshot of the level and Dave's progress, taken when he touches a checkpoint.
+struct Checkpoint {
+    /// Position of the checkpoint tile.
+    pos: (usize, usize),
+    /// The level as it was when the checkpoint was touched.
+    level: Level,
+    /// Whether Dave had the trophy.
+    has_trophy: bool,
+    /// Keys Dave was carrying.
+    inventory: Vec<KeyColor>,
+    /// Score at the time.
+    score: i32,
 }
 
 /// The main game state and engine.
 struct Game {
     /// The 2D grid of tiles for the current level.
-    level: [[Tile; LEVEL_WIDTH]; LEVEL_HEIGHT],
+    level: Level,
+    /// Countdown timers (seconds) for time-based tiles, keyed by tile position.
+    tile_timers: HashMap<(usize, usize), f32>,
+    /// The last checkpoint Dave touched on the current level.
+    checkpoint: Option<Checkpoint>,
     /// The player character.
     player: Player,
-    /// Whether the game is currently running.
-    running: bool,
-    /// Whether the player has won the entire game.
-    won: bool,
-    /// Whether the player is currently dead.
-    is_dead: bool,
-    /// Whether the player has completed the current level.
-    level_complete: bool,
+    /// The screen the game is currently on.
+    screen: Screen,
+    /// Highlighted entry of the menu on the current screen.
+    selected: usize,
+    /// Screen to go back to when leaving the options screen.
+    options_return: Screen,
+    /// Binding waiting for its new key on the options screen.
+    rebinding: Option<Action>,
+    /// Whether focus has come back since play was frozen by losing it.
+    focus_regained: bool,
+    /// Result of the last save or rebind, shown at the bottom of the options screen.
+    options_notice: Option<String>,
     /// The current level number.
     current_level: u32,
     /// Status message displayed at the bottom of the screen.
@@ -54,10 +215,29 @@ struct Game {
     death_timer: f32,
     /// Timer for level start delay (seconds).
     start_timer: f32,
-    /// Current configuration loaded from config.toml or defaults.
+    /// Time spent playing the current level (seconds).
+    level_time: f32,
+    /// Score when the current level started, for the level summary.
+    level_start_score: i32,
+    /// Current configuration, merged from the config files and defaults.
     config: Config,
+    /// Which actions each key triggers, built from `config.keys`.
+    actions: ActionMap,
+    /// Where the configuration was loaded from, and where options are saved.
+    config_paths: ConfigPaths,
+    /// Modification times of the config files when they were last loaded.
+    config_mtimes: Vec<Option<SystemTime>>,
+    /// Time until the config files are next checked for changes (seconds).
+    config_poll_timer: f32,
     /// Current number of lives remaining.
     lives: i32,
+    /// Lives at the start of a game when set with `--lives`, overriding the preset.
+    lives_override: Option<i32>,
+    /// Preset chosen with `--preset`, kept when the config files are reloaded.
+    preset_override: Option<Preset>,
+    /// Master seed the levels of this run are generated from, set with `--seed` or
+    /// picked at random.
+    seed: u32,
     /// Current player score.
     score: i32,
     /// Whether to use ASCII graphics (2-char wide) or older graphics (1-char wide).
@@ -67,8 +247,11 @@ struct Game {
 impl Game {
     /// Creates a new game instance, starting at the specified level.
     fn new(start_level: u32, config: Config, use_ascii: bool) -> Self {
+        let lives = config.preset.lives();
         let mut game = Game {
-            level: [[Tile::Empty; LEVEL_WIDTH]; LEVEL_HEIGHT],
+            level: Level::new(LEVEL_WIDTH, LEVEL_HEIGHT),
+            tile_timers: HashMap::new(),
+            checkpoint: None,
             player: Player {
                 x: 2.0,
                 y: 18.0,
@@ -76,19 +259,35 @@ impl Game {
                 vy: 0.0,
                 on_ground: false,
                 has_trophy: false,
+                inventory: Vec::new(),
                 coyote_timer: 0.0,
                 jump_buffer_timer: 0.0,
+                drop_timer: 0.0,
+                launched: false,
+                in_teleporter: false,
+                invuln_timer: 0.0,
             },
-            running: true,
-            won: false,
-            is_dead: false,
-            level_complete: false,
+            screen: Screen::Title,
+            selected: 0,
+            options_return: Screen::Title,
+            rebinding: None,
+            focus_regained: false,
+            options_notice: None,
             current_level: start_level,
             message: format!("Level {}: Find the Trophy (*) and then reach the Exit (E)!", start_level),
             death_timer: 0.0,
             start_timer: 0.5,
+            level_time: 0.0,
+            level_start_score: 0,
+            actions: ActionMap::new(&config.keys),
             config,
-            lives: 3,
+            config_paths: ConfigPaths::default(),
+            config_mtimes: Vec::new(),
+            config_poll_timer: CONFIG_POLL_INTERVAL,
+            lives,
+            lives_override: None,
+            preset_override: None,
+            seed: 0,
             score: 0,
             use_ascii,
         };
@@ -96,76 +295,410 @@ impl Game {
         game
     }
 
+    /// Lives at the start of a game: the `--lives` value if given, otherwise the preset's.
+    fn starting_lives(&self) -> i32 {
+        self.lives_override.unwrap_or(self.config.preset.lives())
+    }
+
+    /// Adds `points`, scaled by the preset's score multiplier, and returns what was added.
+    fn award(&mut self, points: i32) -> i32 {
+        let points = (points as f32 * self.config.preset.score_mult()).round() as i32;
+        self.score += points;
+        points
+    }
+
     /// Initializes or re-initializes the level based on `current_level`.
     /// Generates a new procedural layout and positions the player.
     fn init_level(&mut self) {
-        let (level, (px, py)) = generate_level(self.current_level);
+        let (level, (px, py)) = generate_level_with(self.seed, self.current_level, &self.config.gen_params());
         self.level = level;
         self.player.x = px;
         self.player.y = py;
+        self.checkpoint = None;
+        self.reset_tile_timers();
+    }
+
+    /// Restarts the timers of time-based tiles: collapsed floors grow back and the
+    /// fire jets are staggered so they don't all fire at once.
+    fn reset_tile_timers(&mut self) {
+        self.tile_timers.clear();
+        for y in 0..self.level.height() {
+            for x in 0..self.level.width() {
+                match self.level[y][x] {
+                    Tile::Collapsed => self.level[y][x] = Tile::Crumbling,
+                    Tile::FireJet { .. } => {
+                        self.tile_timers.insert((x, y), FIRE_JET_OFF_TIME * ((x % 3) as f32 + 1.0) / 3.0);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Advances the timers of time-based tiles and switches tiles whose timer ran out:
+    /// crumbling floors collapse and later respawn, fire jets toggle on and off.
+    fn update_tile_timers(&mut self, dt: f32) {
+        let mut expired = Vec::new();
+        for (&pos, timer) in self.tile_timers.iter_mut() {
+            *timer -= dt;
+            if *timer <= 0.0 {
+                expired.push(pos);
+            }
+        }
+
+        for (x, y) in expired {
+            match self.level[y][x] {
+                Tile::Crumbling => {
+                    self.level[y][x] = Tile::Collapsed;
+                    self.tile_timers.insert((x, y), CRUMBLE_RESPAWN_TIME);
+                }
+                Tile::Collapsed => {
+                    // Don't grow back around Dave; try again shortly
+                    if self.player.x.floor() as usize == x && self.player.y.floor() as usize == y {
+                        self.tile_timers.insert((x, y), 0.1);
+                    } else {
+                        self.level[y][x] = Tile::Crumbling;
+                        self.tile_timers.remove(&(x, y));
+                    }
+                }
+                Tile::FireJet { active } => {
+                    self.level[y][x] = Tile::FireJet { active: !active };
+                    self.tile_timers.insert((x, y), if active { FIRE_JET_OFF_TIME } else { FIRE_JET_ON_TIME });
+                }
+                _ => {
+                    self.tile_timers.remove(&(x, y));
+                }
+            }
+        }
     }
 
     /// Resets the game state for the current level or restarts the game if all lives are lost.
     fn reset(&mut self) {
         if self.lives <= 0 {
-            self.lives = 3;
+            self.lives = self.starting_lives();
             self.score = 0;
             self.current_level = 1;
         }
-        self.player.vx = 0.0;
-        self.player.vy = 0.0;
-        self.player.on_ground = false;
+        self.player.stop();
         self.player.has_trophy = false;
-        self.player.coyote_timer = 0.0;
-        self.player.jump_buffer_timer = 0.0;
-        self.is_dead = false;
-        self.won = false;
-        self.level_complete = false;
+        self.player.inventory.clear();
+        self.player.invuln_timer = 0.0;
+        self.screen = Screen::Playing;
         self.death_timer = 0.0;
         self.start_timer = 0.5;
+        self.level_time = 0.0;
+        self.level_start_score = self.score;
         self.init_level();
         self.message = format!("Level {}: Find the Trophy (*) and then reach the Exit (E)!", self.current_level);
     }
 
-    /// Updates the game state based on elapsed time (`dt`) and keyboard input.
-    /// Handles physics, movement, collisions, and interactions.
-    fn update(&mut self, dt: f32, keys: &HashSet<KeyCode>) {
-        let restart_pressed = keys.iter().any(|&k| self.config.key_matches(k, &self.config.keys.restart));
-
-        if self.is_dead {
-            self.death_timer -= dt;
-            if self.death_timer <= 0.0 && restart_pressed {
+    /// Brings Dave back after losing a life: at the last checkpoint when checkpoint respawn
+    /// is enabled and one was touched, otherwise by restarting the level.
+    fn respawn(&mut self) {
+        let checkpoint = match &self.checkpoint {
+            Some(checkpoint) if self.lives > 0 && self.config.respawn == RespawnMode::Checkpoint => checkpoint,
+            _ => {
                 self.reset();
+                if self.lives > 0 {
+                    self.player.invuln_timer = INVULNERABLE_TIME;
+                }
+                return;
             }
+        };
+
+        self.level = checkpoint.level.clone();
+        self.player.x = checkpoint.pos.0 as f32 + 0.5;
+        self.player.y = checkpoint.pos.1 as f32 + 0.99;
+        self.player.has_trophy = checkpoint.has_trophy;
+        self.player.inventory = checkpoint.inventory.clone();
+        self.score = checkpoint.score;
+        self.player.stop();
+        self.player.invuln_timer = INVULNERABLE_TIME;
+        self.screen = Screen::Playing;
+        self.death_timer = 0.0;
+        self.reset_tile_timers();
+        self.message = format!("Back at the checkpoint. Lives left: {}.", self.lives);
+    }
+
+    /// Switches to another screen, resetting the menu highlight.
+    fn set_screen(&mut self, screen: Screen) {
+        self.screen = screen;
+        self.selected = 0;
+    }
+
+    /// Handles a single key press, either as the new key for a binding being changed
+    /// or as the actions bound to it.
+    fn handle_key(&mut self, key: KeyBinding) {
+        if self.screen == Screen::FocusLost {
+            self.set_screen(Screen::Playing);
             return;
         }
+        if let Some(action) = self.rebinding.take() {
+            self.rebind(action, key);
+            return;
+        }
+        for action in self.actions.actions(key).to_vec() {
+            self.handle_action(action);
+        }
+    }
+
+    /// Handles a pressed action: menu navigation, pausing and screen transitions.
+    /// Held actions for movement are handled by `update` instead.
+    fn handle_action(&mut self, action: Action) {
+        let confirm = action == Action::Restart;
+        let back = action == Action::Quit;
+        let pause = action == Action::Pause;
+        let up = action == Action::Jump;
+        let down = action == Action::Down;
 
-        if self.level_complete {
-            if restart_pressed {
-                if self.current_level < self.config.max_level {
-                    self.current_level += 1;
+        match self.screen {
+            Screen::Title => {
+                if back {
+                    self.set_screen(Screen::Quit);
+                } else if up || down {
+                    self.selected = step_menu(self.selected, TITLE_MENU.len(), up);
+                } else if confirm {
+                    self.choose(TITLE_MENU[self.selected]);
+                }
+            }
+            Screen::Playing => {
+                if back || pause {
+                    self.set_screen(Screen::Paused);
+                }
+            }
+            Screen::Paused => {
+                if back || pause {
+                    self.set_screen(Screen::Playing);
+                } else if up || down {
+                    self.selected = step_menu(self.selected, PAUSE_MENU.len(), up);
+                } else if confirm {
+                    self.choose(PAUSE_MENU[self.selected]);
+                }
+            }
+            Screen::Options => {
+                let left = action == Action::Left;
+                let right = action == Action::Right;
+                if back {
+                    self.close_options();
+                } else if up || down {
+                    self.selected = step_menu(self.selected, OPTION_ROWS.len(), up);
+                } else if left || right {
+                    self.adjust_option(OPTION_ROWS[self.selected], right);
+                } else if confirm {
+                    match OPTION_ROWS[self.selected] {
+                        OptionRow::Binding(action) => self.rebinding = Some(action),
+                        OptionRow::Save => {
+                            let path = self.config_paths.save_target();
+                            self.options_notice = Some(match self.config.save(&path) {
+                                Ok(()) => format!("Options saved to {}.", path.display()),
+                                Err(e) => format!("Could not save {}: {}", path.display(), e),
+                            });
+                        }
+                        OptionRow::Back => self.close_options(),
+                        OptionRow::Preset | OptionRow::Physics(..) | OptionRow::MaxLevel => {}
+                    }
+                }
+            }
+            // Enter and Esc both wait out the same short delay after dying
+            Screen::Dead if self.death_timer > 0.0 => {}
+            Screen::Dead => {
+                if confirm {
+                    self.respawn();
+                } else if back {
+                    // Respawn first, so resuming doesn't put Dave back where he died
+                    self.respawn();
+                    self.set_screen(Screen::Paused);
+                }
+            }
+            Screen::LevelComplete => {
+                if confirm {
+                    if self.current_level < self.config.max_level {
+                        self.current_level += 1;
+                        self.reset();
+                    } else {
+                        self.set_screen(Screen::Won);
+                    }
+                } else if back {
+                    self.set_screen(Screen::Quit);
+                }
+            }
+            Screen::GameOver => {
+                if confirm {
                     self.reset();
-                } else {
-                    self.won = true;
-                    self.running = false;
+                } else if back {
+                    self.set_screen(Screen::Quit);
                 }
             }
+            Screen::Won => {
+                if confirm || back {
+                    self.set_screen(Screen::Quit);
+                }
+            }
+            Screen::FocusLost | Screen::Quit => {}
+        }
+    }
+
+    /// Carries out the title or pause menu entry picked with ENTER.
+    fn choose(&mut self, item: MenuItem) {
+        match item {
+            MenuItem::StartGame | MenuItem::Resume => self.set_screen(Screen::Playing),
+            MenuItem::RestartLevel => self.reset(),
+            MenuItem::Options => self.open_options(),
+            MenuItem::Quit => self.set_screen(Screen::Quit),
+        }
+    }
+
+    /// Freezes play when the terminal loses focus, so Dave doesn't run on unattended.
+    fn focus_lost(&mut self) {
+        if self.screen == Screen::Playing {
+            self.focus_regained = false;
+            self.set_screen(Screen::FocusLost);
+        }
+    }
+
+    /// Notes that the terminal has focus again, so the resume prompt can be shown.
+    fn focus_gained(&mut self) {
+        self.focus_regained = true;
+    }
+
+    /// Opens the options screen, remembering where to return to.
+    fn open_options(&mut self) {
+        self.options_return = self.screen;
+        self.options_notice = None;
+        self.set_screen(Screen::Options);
+    }
+
+    /// Leaves the options screen. Changes stay in effect for this session even if unsaved.
+    fn close_options(&mut self) {
+        let screen = self.options_return;
+        self.set_screen(screen);
+    }
+
+    /// Steps a numeric option up or down.
+    fn adjust_option(&mut self, row: OptionRow, increase: bool) {
+        let sign = if increase { 1.0 } else { -1.0 };
+        match row {
+            OptionRow::Physics(name, step) => {
+                if let Some(value) = self.config.physics.field_mut(name) {
+                    let old = *value;
+                    *value = ((old + sign * step) / step).round() * step;
+                    // Don't step outside the range config validation accepts
+                    if self.config.physics.out_of_range().iter().any(|&(n, _)| n == name) {
+                        *self.config.physics.field_mut(name).unwrap() = old;
+                    }
+                }
+            }
+            OptionRow::MaxLevel => {
+                self.config.max_level = self.config.max_level.saturating_add_signed(sign as i32).max(1);
+            }
+            OptionRow::Preset => {
+                self.config.preset = self.config.preset.cycle(increase);
+                self.preset_override = None;
+            }
+            OptionRow::Binding(_) | OptionRow::Save | OptionRow::Back => {}
+        }
+    }
+
+    /// Adds the key that was just pressed to the keys bound to `action`.
+    fn rebind(&mut self, action: Action, key: KeyBinding) {
+        let notice = match key.name() {
+            Some(name) if self.actions.actions(key).contains(&action) => {
+                format!("{} is already bound to {}.", action.name(), name)
+            }
+            Some(name) => {
+                let others: Vec<&str> = self.actions.actions(key).iter().map(|a| a.name()).collect();
+                let notice = if others.is_empty() {
+                    format!("{} is now also bound to {}.", action.name(), name)
+                } else {
+                    format!("{} is now also bound to {}, which is also {}.", action.name(), name, others.join(" and "))
+                };
+                // The new key joins the ones already bound rather than replacing them
+                self.config.keys.binding_mut(action).push(name);
+                self.actions = ActionMap::new(&self.config.keys);
+                notice
+            }
+            None => format!("That key can't be bound; {} is unchanged.", action.name()),
+        };
+        self.options_notice = Some(notice);
+    }
+
+    /// Updates the game state based on elapsed time (`dt`) and the currently held keys.
+    /// Only advances the simulation while playing (or waiting to respawn).
+    fn update(&mut self, dt: f32, held: &HashSet<Action>) {
+        self.poll_config(dt);
+        match self.screen {
+            Screen::Playing => self.update_playing(dt, held),
+            Screen::Dead => self.death_timer -= dt,
+            _ => {}
+        }
+    }
+
+    /// Reloads the configuration when any config file has changed since it was loaded.
+    /// A config with errors is rejected and the current one kept.
+    fn poll_config(&mut self, dt: f32) {
+        self.config_poll_timer -= dt;
+        if self.config_poll_timer > 0.0 {
             return;
         }
+        self.config_poll_timer = CONFIG_POLL_INTERVAL;
+
+        let mtimes = self.config_paths.modified_times();
+        if mtimes == self.config_mtimes {
+            return;
+        }
+        self.config_mtimes = mtimes;
+
+        let (mut config, issues) = Config::load_from(&self.config_paths);
+        if let Some(preset) = self.preset_override {
+            config.preset = preset;
+        }
+        self.message = match issues.iter().find(|issue| issue.is_error()) {
+            Some(error) => format!("Config not reloaded, {}", error),
+            None => {
+                self.actions = ActionMap::new(&config.keys);
+                self.config = config;
+                match issues.len() {
+                    0 => "Config reloaded.".to_string(),
+                    n => format!("Config reloaded with {} warning(s): {}", n, issues[0]),
+                }
+            }
+        };
+    }
 
+    /// Advances level play by `dt`.
+    /// Handles physics, movement, collisions, and interactions.
+    fn update_playing(&mut self, dt: f32, held: &HashSet<Action>) {
         if self.start_timer > 0.0 {
             self.start_timer -= dt;
             return;
         }
 
         // Update timers
+        self.level_time += dt;
         self.player.coyote_timer -= dt;
         self.player.jump_buffer_timer -= dt;
+        self.player.drop_timer -= dt;
+        self.player.invuln_timer -= dt;
+        self.update_tile_timers(dt);
 
         // Key states from config
-        let left_pressed = keys.iter().any(|&k| self.config.key_matches(k, &self.config.keys.left));
-        let right_pressed = keys.iter().any(|&k| self.config.key_matches(k, &self.config.keys.right));
-        let jump_pressed = keys.iter().any(|&k| self.config.key_matches(k, &self.config.keys.jump));
+        let left_pressed = held.contains(&Action::Left);
+        let right_pressed = held.contains(&Action::Right);
+        let jump_pressed = held.contains(&Action::Jump);
+        let down_pressed = held.contains(&Action::Down);
+
+        // Surface Dave is standing on, for ice and conveyor belts
+        let surface = if self.player.on_ground {
+            self.tile_underfoot().map(|(fx, fy)| self.level[fy][fx])
+        } else {
+            None
+        };
+        let on_ice = surface == Some(Tile::Ice);
+        let belt_vx = match surface {
+            Some(Tile::ConveyorLeft) => -self.config.conveyor.vx,
+            Some(Tile::ConveyorRight) => self.config.conveyor.vx,
+            _ => 0.0,
+        };
 
         // Horizontal movement
         let mut target_vx = 0.0;
@@ -181,14 +714,26 @@ impl Game {
         
         // Acceleration/Friction
         if moving {
-            let accel = if self.player.on_ground { self.config.physics.accel_ground } else { self.config.physics.accel_air };
+            let accel = if on_ice {
+                self.config.physics.accel_ground * self.config.ice.accel_mult
+            } else if self.player.on_ground {
+                self.config.physics.accel_ground
+            } else {
+                self.config.physics.accel_air
+            };
             if self.player.vx < target_vx {
                 self.player.vx = (self.player.vx + accel * dt).min(target_vx);
             } else if self.player.vx > target_vx {
                 self.player.vx = (self.player.vx - accel * dt).max(target_vx);
             }
         } else {
-            let friction = if self.player.on_ground { self.config.physics.friction } else { self.config.physics.friction * 0.5 };
+            let friction = if on_ice {
+                self.config.physics.friction * self.config.ice.friction_mult
+            } else if self.player.on_ground {
+                self.config.physics.friction
+            } else {
+                self.config.physics.friction * 0.5
+            };
             if self.player.vx > 0.0 {
                 self.player.vx = (self.player.vx - friction * dt).max(0.0);
             } else if self.player.vx < 0.0 {
@@ -196,9 +741,14 @@ impl Game {
             }
         }
 
-        // Jump input and buffering
-        if jump_pressed {
-            self.player.jump_buffer_timer = self.config.physics.jump_buffer_time;
+        // Jump input and buffering; Down + Jump on a one-way platform drops through it instead
+        if down_pressed && jump_pressed && self.player.on_ground && self.is_on_platform() {
+            self.player.drop_timer = DROP_THROUGH_TIME;
+            self.player.on_ground = false;
+            self.player.coyote_timer = 0.0;
+            self.player.jump_buffer_timer = 0.0;
+        } else if jump_pressed {
+            self.player.jump_buffer_timer = self.config.physics.jump_buffer_time * self.config.preset.jump_timing_mult();
         }
 
         // Jump logic (Coyote time and Buffer)
@@ -210,7 +760,7 @@ impl Game {
         }
 
         // Gravity with variable jump height
-        let gravity = if self.player.vy < 0.0 && !jump_pressed {
+        let gravity = if self.player.vy < 0.0 && !jump_pressed && !self.player.launched {
             self.config.physics.gravity * self.config.physics.jump_release_gravity_mult
         } else {
             self.config.physics.gravity
@@ -219,92 +769,204 @@ impl Game {
 
         // Vertical movement and collision
         let next_y = self.player.y + self.player.vy * dt;
-        if self.is_colliding(self.player.x, next_y) {
+        if self.is_colliding(self.player.x, next_y, self.player.y) {
             if self.player.vy > 0.0 {
                 self.player.on_ground = true;
-                self.player.coyote_timer = self.config.physics.coyote_time;
-                self.player.y = next_y.floor() as f32 - 0.01;
+                self.player.coyote_timer = self.config.physics.coyote_time * self.config.preset.jump_timing_mult();
+                self.player.y = next_y.floor() - 0.01;
             } else {
-                self.player.y = next_y.floor() as f32 + 1.0;
+                self.player.y = next_y.floor() + 1.0;
             }
             self.player.vy = 0.0;
         } else {
             self.player.y = next_y;
             // Robust on-ground check: are we standing on a wall?
-            if self.is_colliding(self.player.x, self.player.y + 0.1) {
+            if self.is_colliding(self.player.x, self.player.y + 0.1, self.player.y) {
                 self.player.on_ground = true;
-                self.player.coyote_timer = self.config.physics.coyote_time;
+                self.player.coyote_timer = self.config.physics.coyote_time * self.config.preset.jump_timing_mult();
             } else {
                 self.player.on_ground = false;
             }
         }
 
-        // Horizontal movement and collision
-        let next_x = self.player.x + self.player.vx * dt;
-        if self.is_colliding(next_x, self.player.y) {
+        // Crumbling floors start to give way once Dave stands on them
+        if self.player.on_ground && let Some((fx, fy)) = self.tile_underfoot() && self.level[fy][fx] == Tile::Crumbling {
+            self.tile_timers.entry((fx, fy)).or_insert(CRUMBLE_DELAY);
+        }
+
+        // Springs launch Dave as soon as he lands on them
+        if self.player.on_ground {
+            self.player.launched = false;
+            if let Some((fx, fy)) = self.tile_underfoot() && self.level[fy][fx] == Tile::Spring {
+                self.player.vy = self.config.spring.vy;
+                self.player.on_ground = false;
+                self.player.coyote_timer = 0.0;
+                self.player.launched = true;
+            }
+        }
+
+        // Horizontal movement and collision (conveyor belts carry Dave along)
+        let next_x = self.player.x + (self.player.vx + belt_vx) * dt;
+        if self.is_colliding(next_x, self.player.y, self.player.y) {
+            if let Some(color) = self.locked_door_at(next_x, self.player.y) {
+                self.message = format!("This door needs the {} key!", color.name());
+            }
             self.player.vx = 0.0;
             if next_x > self.player.x {
-                self.player.x = next_x.floor() as f32 - 0.01;
+                self.player.x = next_x.floor() - 0.01;
             } else {
-                self.player.x = next_x.floor() as f32 + 1.0;
+                self.player.x = next_x.floor() + 1.0;
             }
         } else {
             self.player.x = next_x;
         }
 
+        // Falling out through a pit in the floor; the X marks the pit he went down
+        if self.player.y >= self.level.height() as f32 {
+            self.player.y = self.level.height() as f32 - 0.01;
+            self.lose_life("You fell into a pit!");
+            return;
+        }
+
         // Interaction
         let tx = self.player.x.floor() as usize;
         let ty = self.player.y.floor() as usize;
         
-        if tx < LEVEL_WIDTH && ty < LEVEL_HEIGHT {
+        if tx < self.level.width() && ty < self.level.height() {
+            if !matches!(self.level[ty][tx], Tile::Teleporter(_)) {
+                self.player.in_teleporter = false;
+            }
             match self.level[ty][tx] {
                 Tile::Trophy => {
                     self.player.has_trophy = true;
                     self.level[ty][tx] = Tile::Empty;
-                    self.score += 500;
-                    self.message = "Got the Trophy! +500 points. Now reach the Exit (E)!".to_string();
+                    let points = self.award(500);
+                    self.message = format!("Got the Trophy! +{} points. Now reach the Exit (E)!", points);
+                }
+                Tile::Key(color) => {
+                    self.player.inventory.push(color);
+                    self.level[ty][tx] = Tile::Empty;
+                    let points = self.award(200);
+                    self.message = format!("Picked up the {} key! +{} points. {} doors are open now.", color.name(), points, color.name());
+                }
+                Tile::Teleporter(_) if !self.player.in_teleporter => {
+                    if let Some((px, py)) = teleporter_partner(&self.level, (tx, ty)) {
+                        self.player.x = px as f32 + 0.5;
+                        self.player.y = py as f32 + 0.99;
+                        if !self.config.teleporter.preserve_velocity {
+                            self.player.vx = 0.0;
+                            self.player.vy = 0.0;
+                        }
+                        self.player.in_teleporter = true;
+                    }
+                }
+                Tile::Warp => {
+                    let target = (self.current_level + self.config.warp.skip_levels).min(self.config.max_level);
+                    self.current_level = target;
+                    self.reset();
+                    self.message = format!("WARP ZONE! Skipped ahead to level {}. Find the Trophy (*) and then reach the Exit (E)!", target);
                 }
                 Tile::Diamond => {
-                    self.score += 100;
+                    let points = self.award(100);
                     self.level[ty][tx] = Tile::Empty;
-                    self.message = "Collected a Diamond! +100 points".to_string();
+                    self.message = format!("Collected a Diamond! +{} points", points);
                 }
                 Tile::Exit => {
                     if self.player.has_trophy {
-                        self.level_complete = true;
-                        self.score += 1000;
+                        self.set_screen(Screen::LevelComplete);
+                        let points = self.award(1000);
                         if self.current_level < self.config.max_level {
-                            self.message = "Level Complete! +1000 points. Press ENTER for next level.".to_string();
+                            self.message = format!("Level Complete! +{} points. Press ENTER for next level.", points);
                         } else {
-                            self.message = "All Levels Complete! +1000 points. Press ENTER to win!".to_string();
+                            self.message = format!("All Levels Complete! +{} points. Press ENTER to win!", points);
                         }
                     } else {
                         self.message = "You need the Trophy (*) first!".to_string();
                     }
                 }
-                Tile::Hazard => {
-                    self.is_dead = true;
-                    self.death_timer = 0.5;
-                    self.lives -= 1;
-                    if self.lives > 0 {
-                        self.message = format!("Ouch! You hit a hazard! Lives left: {}. Press ENTER to restart.", self.lives);
-                    } else {
-                        self.message = "GAME OVER! You ran out of lives. Press ENTER to restart game.".to_string();
-                    }
+                // Only snapshot on arrival, not on every frame spent standing here
+                Tile::Checkpoint if self.checkpoint.as_ref().map(|c| c.pos) != Some((tx, ty)) => {
+                    self.message = "Checkpoint reached! You'll respawn here.".to_string();
+                    self.checkpoint = Some(Checkpoint {
+                        pos: (tx, ty),
+                        level: self.level.clone(),
+                        has_trophy: self.player.has_trophy,
+                        inventory: self.player.inventory.clone(),
+                        score: self.score,
+                    });
+                }
+                Tile::Hazard | Tile::FireJet { active: true } if self.player.invuln_timer <= 0.0 => {
+                    self.lose_life("You hit a hazard!");
                 }
                 _ => {}
             }
         }
     }
 
+    /// Takes a life for `cause` and shows the death screen, or game over on the last life.
+    fn lose_life(&mut self, cause: &str) {
+        self.death_timer = 0.5;
+        self.lives -= 1;
+        if self.lives > 0 {
+            self.set_screen(Screen::Dead);
+            self.message = format!("Ouch! {} Lives left: {}. Press ENTER to restart.", cause, self.lives);
+        } else {
+            self.set_screen(Screen::GameOver);
+            self.message = "GAME OVER! You ran out of lives. Press ENTER to restart game.".to_string();
+        }
+    }
+
     /// Checks if a given coordinate (x, y) collides with a wall.
-    fn is_colliding(&self, x: f32, y: f32) -> bool {
+    /// One-way platforms only count when coming from a row above them (`prev_y`),
+    /// and not while Dave is dropping through. Beyond the sides and top is solid, but
+    /// below the bottom row is open, so Dave can fall out through a pit.
+    fn is_colliding(&self, x: f32, y: f32, prev_y: f32) -> bool {
         let tx = x.floor() as i32;
         let ty = y.floor() as i32;
-        if tx < 0 || tx >= LEVEL_WIDTH as i32 || ty < 0 || ty >= LEVEL_HEIGHT as i32 {
+        if tx < 0 || tx >= self.level.width() as i32 || ty < 0 {
             return true;
         }
-        self.level[ty as usize][tx as usize] == Tile::Wall
+        if ty >= self.level.height() as i32 {
+            return false;
+        }
+        match self.level[ty as usize][tx as usize] {
+            Tile::Wall | Tile::Crumbling | Tile::Spring | Tile::ConveyorLeft | Tile::ConveyorRight | Tile::Ice => true,
+            Tile::Platform => self.player.drop_timer <= 0.0 && prev_y.floor() < y.floor(),
+            Tile::Door(color) => !self.player.inventory.contains(&color),
+            _ => false,
+        }
+    }
+
+    /// Returns the color of the locked door at (x, y), if there is one.
+    fn locked_door_at(&self, x: f32, y: f32) -> Option<KeyColor> {
+        let tx = x.floor() as usize;
+        let ty = y.floor() as usize;
+        if tx >= self.level.width() || ty >= self.level.height() {
+            return None;
+        }
+        match self.level[ty][tx] {
+            Tile::Door(color) if !self.player.inventory.contains(&color) => Some(color),
+            _ => None,
+        }
+    }
+
+    /// Returns the position of the tile directly under Dave's feet, if it is inside the level.
+    fn tile_underfoot(&self) -> Option<(usize, usize)> {
+        let tx = self.player.x.floor() as usize;
+        let ty = (self.player.y + 0.1).floor() as usize;
+        (tx < self.level.width() && ty < self.level.height()).then_some((tx, ty))
+    }
+
+    /// Checks if Dave is standing on a one-way platform.
+    fn is_on_platform(&self) -> bool {
+        self.tile_underfoot().is_some_and(|(tx, ty)| self.level[ty][tx] == Tile::Platform)
+    }
+
+    /// First level row shown on screen. Levels taller than the screen scroll to keep
+    /// Dave near the middle.
+    fn camera_top(&self) -> usize {
+        let max_top = self.level.height().saturating_sub(LEVEL_HEIGHT);
+        (self.player.y as usize).saturating_sub(LEVEL_HEIGHT / 2).min(max_top)
     }
 
     /// Renders the current game state to the terminal.
@@ -315,17 +977,21 @@ impl Game {
         
         let mut buffer = String::with_capacity(LEVEL_WIDTH * LEVEL_HEIGHT * 10);
         
-        for y in 0..LEVEL_HEIGHT {
-            for x in 0..LEVEL_WIDTH {
-                if x == self.player.x.floor() as usize && y == self.player.y.floor() as usize {
+        let top = self.camera_top();
+        for y in top..(top + LEVEL_HEIGHT).min(self.level.height()) {
+            for x in 0..self.level.width() {
+                // Dave blinks while invulnerable
+                let blinking = self.player.invuln_timer > 0.0 && (self.player.invuln_timer * 10.0) as i32 % 2 == 1;
+                if x == self.player.x.floor() as usize && y == self.player.y.floor() as usize && !blinking {
+                    let dead = matches!(self.screen, Screen::Dead | Screen::GameOver);
                     if self.use_ascii {
-                        if self.is_dead {
+                        if dead {
                             buffer.push_str("\x1b[31mX \x1b[0m"); // Red X for dead Dave
                         } else {
                             buffer.push_str("\x1b[36m☺ \x1b[0m"); // Cyan Dave (Smile)
                         }
                     } else {
-                        if self.is_dead {
+                        if dead {
                             buffer.push_str("\x1b[31mX\x1b[0m"); // Red X for dead Dave
                         } else {
                             buffer.push_str("\x1b[36mD\x1b[0m"); // Cyan Dave (Letter D)
@@ -339,6 +1005,42 @@ impl Game {
                         Tile::Exit => buffer.push_str(if self.use_ascii { "\x1b[32m][\x1b[0m" } else { "\x1b[32mE\x1b[0m" }),
                         Tile::Hazard => buffer.push_str(if self.use_ascii { "\x1b[31m▲▲\x1b[0m" } else { "\x1b[31m^\x1b[0m" }),
                         Tile::Diamond => buffer.push_str(if self.use_ascii { "\x1b[35m♦ \x1b[0m" } else { "\x1b[35m+\x1b[0m" }),
+                        Tile::Platform => buffer.push_str(if self.use_ascii { "\x1b[34m▀▀\x1b[0m" } else { "\x1b[34m=\x1b[0m" }),
+                        Tile::Key(color) => {
+                            buffer.push_str(key_color_code(color));
+                            buffer.push_str(if self.use_ascii { "o¬\x1b[0m" } else { "k\x1b[0m" });
+                        }
+                        Tile::Crumbling => {
+                            // Cracks appear once the floor has started to give way
+                            if self.tile_timers.contains_key(&(x, y)) {
+                                buffer.push_str(if self.use_ascii { "\x1b[33m░░\x1b[0m" } else { "\x1b[33m~\x1b[0m" });
+                            } else {
+                                buffer.push_str(if self.use_ascii { "\x1b[33m▒▒\x1b[0m" } else { "\x1b[33m%\x1b[0m" });
+                            }
+                        }
+                        Tile::Collapsed => buffer.push_str(if self.use_ascii { "\x1b[90m..\x1b[0m" } else { "\x1b[90m.\x1b[0m" }),
+                        Tile::FireJet { active: true } => buffer.push_str(if self.use_ascii { "\x1b[91m╫╫\x1b[0m" } else { "\x1b[91m!\x1b[0m" }),
+                        Tile::FireJet { active: false } => buffer.push_str(if self.use_ascii { "\x1b[90m┴┴\x1b[0m" } else { "\x1b[90mi\x1b[0m" }),
+                        Tile::Spring => buffer.push_str(if self.use_ascii { "\x1b[92m◘◘\x1b[0m" } else { "\x1b[92ms\x1b[0m" }),
+                        Tile::ConveyorLeft => buffer.push_str(if self.use_ascii { "\x1b[37m<<\x1b[0m" } else { "\x1b[37m<\x1b[0m" }),
+                        Tile::ConveyorRight => buffer.push_str(if self.use_ascii { "\x1b[37m>>\x1b[0m" } else { "\x1b[37m>\x1b[0m" }),
+                        Tile::Ice => buffer.push_str(if self.use_ascii { "\x1b[96m▓▓\x1b[0m" } else { "\x1b[96m-\x1b[0m" }),
+                        Tile::Teleporter(_) => buffer.push_str(if self.use_ascii { "\x1b[95m@@\x1b[0m" } else { "\x1b[95m@\x1b[0m" }),
+                        // Warp zones are secret, so they look like empty space
+                        Tile::Warp => buffer.push_str(if self.use_ascii { "  " } else { " " }),
+                        Tile::Checkpoint => {
+                            let active = self.checkpoint.as_ref().is_some_and(|c| c.pos == (x, y));
+                            buffer.push_str(if active { "\x1b[92m" } else { "\x1b[37m" });
+                            buffer.push_str(if self.use_ascii { "|>\x1b[0m" } else { "f\x1b[0m" });
+                        }
+                        Tile::Door(color) => {
+                            buffer.push_str(key_color_code(color));
+                            if self.player.inventory.contains(&color) {
+                                buffer.push_str(if self.use_ascii { "░░\x1b[0m" } else { "'\x1b[0m" });
+                            } else {
+                                buffer.push_str(if self.use_ascii { "▌▐\x1b[0m" } else { "|\x1b[0m" });
+                            }
+                        }
                     }
                 }
             }
@@ -352,10 +1054,10 @@ impl Game {
             Clear(ClearType::CurrentLine),
         )?;
 
-        if self.is_dead {
-            queue!(stdout, SetForegroundColor(Color::Red))?;
-        } else if self.level_complete {
-            queue!(stdout, SetForegroundColor(Color::Green))?;
+        match self.screen {
+            Screen::Dead | Screen::GameOver => queue!(stdout, SetForegroundColor(Color::Red))?,
+            Screen::LevelComplete | Screen::Won => queue!(stdout, SetForegroundColor(Color::Green))?,
+            _ => {}
         }
 
         queue!(
@@ -364,78 +1066,343 @@ impl Game {
             ResetColor,
             cursor::MoveTo(0, (LEVEL_HEIGHT + 2) as u16),
             Clear(ClearType::CurrentLine),
-            Print(format!("Score: {:06} | Lives: {} | Trophy: {} | Pos: ({:.1}, {:.1})", 
+            Print(format!("Score: {:06} | Lives: {} | Trophy: {} | Keys: ", 
                 self.score,
                 self.lives,
-                if self.player.has_trophy { "YES" } else { "NO" },
-                self.player.x, self.player.y))
+                if self.player.has_trophy { "YES" } else { "NO" })),
         )?;
+
+        if self.player.inventory.is_empty() {
+            queue!(stdout, Print("-"))?;
+        }
+        for &color in &self.player.inventory {
+            queue!(stdout, Print(format!("{}{}\x1b[0m ", key_color_code(color), color.name())))?;
+        }
+        queue!(stdout, Print(format!(" | Pos: ({:.1}, {:.1})", self.player.x, self.player.y)))?;
+
+        if let Some(lines) = self.overlay_lines() {
+            draw_overlay(stdout, &lines, self.use_ascii)?;
+        }
         
         stdout.flush()?;
         Ok(())
     }
+
+    /// Text of the box drawn over the level for menus and summaries, if any.
+    fn overlay_lines(&self) -> Option<Vec<String>> {
+        let menu = |title: &str, entries: &[&str]| {
+            let mut lines = vec![title.to_string(), String::new()];
+            for (i, entry) in entries.iter().enumerate() {
+                let marker = if i == self.selected { ">" } else { " " };
+                lines.push(format!("{} {}", marker, entry));
+            }
+            lines
+        };
+
+        let lines = match self.screen {
+            Screen::Title => {
+                let mut lines = menu("R U S T Y   D A V E", &TITLE_MENU.map(MenuItem::label));
+                lines.push(String::new());
+                lines.push(format!("Starting at level {}, seed {}", self.current_level, self.seed));
+                lines.push(format!("Difficulty: {}", self.config.preset.name()));
+                lines
+            }
+            Screen::Paused => {
+                let mut lines = menu("PAUSED", &PAUSE_MENU.map(MenuItem::label));
+                lines.push(String::new());
+                lines.push(format!("Level {}, seed {}", self.current_level, self.seed));
+                lines
+            }
+            Screen::Options => {
+                let physics = self.config.physics.fields();
+                let rows: Vec<String> = OPTION_ROWS
+                    .iter()
+                    .map(|row| match *row {
+                        OptionRow::Physics(name, _) => {
+                            let value = physics.iter().find(|(n, _)| *n == name).map_or(0.0, |&(_, v)| v);
+                            format!("{:<26}{:.2}", name, value)
+                        }
+                        OptionRow::Preset => format!("{:<26}{}", "preset", self.config.preset.name()),
+                        OptionRow::MaxLevel => format!("{:<26}{}", "max_level", self.config.max_level),
+                        OptionRow::Binding(action) if self.rebinding == Some(action) => {
+                            format!("{:<26}<press a key>", action.name())
+                        }
+                        OptionRow::Binding(action) => {
+                            format!("{:<26}{}", action.name(), self.config.keys.binding(action).join(" "))
+                        }
+                        OptionRow::Save => "Save to config.toml".to_string(),
+                        OptionRow::Back => "Back".to_string(),
+                    })
+                    .collect();
+                let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+                let mut lines = menu("OPTIONS", &rows);
+                // Scroll the rows to keep the highlighted one in view
+                let first = self.selected.saturating_sub(OPTION_ROWS_SHOWN / 2).min(rows.len() - OPTION_ROWS_SHOWN);
+                lines.drain(2..2 + first);
+                lines.truncate(2 + OPTION_ROWS_SHOWN);
+                let hint = "Left/Right change values, ENTER adds a key";
+                lines.push(self.options_notice.clone().unwrap_or_else(|| hint.to_string()));
+                lines
+            }
+            Screen::LevelComplete => vec![
+                format!("LEVEL {} COMPLETE", self.current_level),
+                String::new(),
+                format!("Time:   {:.1}s", self.level_time),
+                format!("Points: {}", self.score - self.level_start_score),
+                format!("Score:  {:06}", self.score),
+                String::new(),
+                "Press ENTER to continue".to_string(),
+            ],
+            Screen::GameOver => vec![
+                "GAME OVER".to_string(),
+                String::new(),
+                format!("Final score: {:06}", self.score),
+                String::new(),
+                "ENTER to try again, Q to quit".to_string(),
+            ],
+            Screen::Won => vec![
+                "CONGRATULATIONS!".to_string(),
+                String::new(),
+                "You escaped with the trophy!".to_string(),
+                format!("Final score: {:06}", self.score),
+                String::new(),
+                "Press ENTER to quit".to_string(),
+            ],
+            Screen::FocusLost if self.focus_regained => vec!["Paused \u{2014} press any key".to_string()],
+            Screen::Playing | Screen::Dead | Screen::FocusLost | Screen::Quit => return None,
+        };
+        Some(lines)
+    }
 }
 
-fn parse_args(args: &[String], max_level: u32) -> (u32, bool) {
-    let mut start_level = 1;
-    let mut use_ascii = false;
-    for arg in args.iter().skip(1) {
-        if arg == "--ascii" {
-            use_ascii = true;
-        } else if let Ok(level) = arg.parse::<u32>() {
-            start_level = level.clamp(1, max_level);
-        }
+/// Moves a menu highlight one entry up or down, wrapping around at the ends.
+fn step_menu(selected: usize, len: usize, up: bool) -> usize {
+    if up { (selected + len - 1) % len } else { (selected + 1) % len }
+}
+
+/// Draws a bordered box of text centred over the level area.
+fn draw_overlay(stdout: &mut io::Stdout, lines: &[String], use_ascii: bool) -> io::Result<()> {
+    let screen_width = if use_ascii { LEVEL_WIDTH * 2 } else { LEVEL_WIDTH };
+    let inner = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) + 4;
+    let left = (screen_width.saturating_sub(inner + 2) / 2) as u16;
+    let top = ((LEVEL_HEIGHT + 1).saturating_sub(lines.len() + 2) / 2) as u16;
+
+    let border = format!("+{}+", "-".repeat(inner));
+    queue!(stdout, SetForegroundColor(Color::Yellow), cursor::MoveTo(left, top), Print(&border))?;
+    for (i, line) in lines.iter().enumerate() {
+        queue!(
+            stdout,
+            cursor::MoveTo(left, top + 1 + i as u16),
+            Print(format!("|  {:<width$}|", line, width = inner - 2)),
+        )?;
     }
-    (start_level, use_ascii)
+    queue!(stdout, cursor::MoveTo(left, top + 1 + lines.len() as u16), Print(&border), ResetColor)?;
+    Ok(())
 }
 
-/// Entry point for the Rusty Dave game.
-/// Sets up the terminal, runs the game loop, and cleans up on exit.
-fn main() -> io::Result<()> {
+/// ANSI escape sequence for drawing a key or door of the given color.
+fn key_color_code(color: KeyColor) -> &'static str {
+    match color {
+        KeyColor::Red => "\x1b[91m",
+        KeyColor::Green => "\x1b[92m",
+        KeyColor::Blue => "\x1b[94m",
+    }
+}
+
+/// The game's command line.
+const CLI: Cli = Cli {
+    name: "rustydave",
+    about: "A terminal platformer: find the trophy, then reach the exit.",
+    options: &[Opt::Level, Opt::Seed, Opt::Lives, Opt::Preset, Opt::Graphics, Opt::Ascii, Opt::CheckConfig],
+    level_positional: true,
+};
+
+/// A seed for a run started without `--seed`, taken from the clock.
+fn random_seed() -> u32 {
+    let nanos = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+    (nanos ^ (nanos >> 32)) as u32
+}
+
+/// Whether the terminal is currently set up for the game.
+static TERMINAL_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Keeps the terminal set up for the game while it's alive and restores it when
+/// dropped, so an early return or a panic doesn't leave the shell in raw mode.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> io::Result<Self> {
+        setup_terminal()?;
+        Ok(TerminalGuard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+/// Switches to raw mode on a cleared alternate screen.
+fn setup_terminal() -> io::Result<()> {
     enable_raw_mode()?;
+    TERMINAL_ACTIVE.store(true, Ordering::SeqCst);
     let mut stdout = io::stdout();
     execute!(
         stdout, 
         EnterAlternateScreen, 
         cursor::Hide, 
         Clear(ClearType::All),
+        EnableFocusChange,
     )?;
 
     // Try to enable keyboard enhancement for better input handling (e.g. in Windows Terminal or modern Unix terminals)
     // We ignore the error if it's not supported (e.g. in legacy Windows Console)
-    let _ = execute!(stdout, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES));
+    // Disambiguating escape codes also lets the terminal tell number pad keys apart
+    let _ = execute!(
+        stdout,
+        PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES | KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES),
+    );
+    Ok(())
+}
+
+/// Undoes `setup_terminal`. Does nothing if the terminal isn't set up, so it is safe
+/// to call more than once and from the panic hook.
+fn restore_terminal() {
+    if !TERMINAL_ACTIVE.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    let mut stdout = io::stdout();
+    let _ = execute!(stdout, PopKeyboardEnhancementFlags, DisableFocusChange, cursor::Show, LeaveAlternateScreen);
+    let _ = disable_raw_mode();
+}
+
+/// Restores the terminal before the default hook prints a panic, so the message
+/// lands on the normal screen.
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        default_hook(info);
+    }));
+}
 
-    let config = Config::load();
-    let args: Vec<String> = std::env::args().collect();
-    let (start_level, use_ascii) = parse_args(&args, config.max_level);
+/// Sets a flag when the process is asked to stop (SIGINT, SIGTERM or SIGHUP), so the
+/// game loop can exit cleanly.
+fn quit_signal() -> io::Result<Arc<AtomicBool>> {
+    let flag = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    for signal in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM, signal_hook::consts::SIGHUP] {
+        signal_hook::flag::register(signal, Arc::clone(&flag))?;
+    }
+    Ok(flag)
+}
+
+/// Hands the terminal back to the shell and stops the process, as Ctrl+Z normally
+/// would outside raw mode. Sets the terminal up again once the shell resumes us.
+#[cfg(unix)]
+fn suspend() -> io::Result<()> {
+    restore_terminal();
+    signal_hook::low_level::raise(signal_hook::consts::SIGTSTP)?;
+    setup_terminal()
+}
 
-    let mut game = Game::new(start_level, config, use_ascii);
+/// Entry point for the Rusty Dave game.
+/// Sets up the terminal, runs the game loop, and cleans up on exit.
+fn main() -> io::Result<()> {
+    let args = CLI.parse_env();
+    let config_paths = ConfigPaths::new(args.config.as_deref());
+    let (mut config, issues) = Config::load_from(&config_paths);
+    for issue in &issues {
+        eprintln!("{}", issue);
+    }
+    if args.check_config {
+        for layer in config_paths.layers.iter().filter(|l| l.path.exists()) {
+            println!("read {}", layer.path.display());
+        }
+        if issues.is_empty() {
+            println!("Configuration is valid.");
+        }
+        std::process::exit(if issues.iter().any(ConfigIssue::is_error) { 1 } else { 0 });
+    }
+
+    install_panic_hook();
+    let quit_requested = quit_signal()?;
+    let guard = TerminalGuard::enter()?;
+    let mut stdout = io::stdout();
+
+    if let Some(preset) = args.preset {
+        config.preset = preset;
+    }
+    let start_level = args.level.unwrap_or(1).clamp(1, config.max_level);
+    let mut game = Game::new(start_level, config, args.graphics == Graphics::Ascii);
+    game.preset_override = args.preset;
+    if let Some(lives) = args.lives {
+        game.lives_override = Some(lives as i32);
+        game.lives = lives as i32;
+    }
+    game.seed = args.seed.unwrap_or_else(random_seed);
+    game.init_level();
+    game.config_mtimes = config_paths.modified_times();
+    game.config_paths = config_paths;
     let mut last_tick = Instant::now();
-    let mut keys = HashSet::new();
+    let releases_reported = supports_keyboard_enhancement().unwrap_or(false);
+    let mut keys = HeldKeys::new(&game.config.input, releases_reported);
 
-    while game.running {
+    while game.screen != Screen::Quit {
+        if quit_requested.load(Ordering::SeqCst) {
+            break;
+        }
         let now = Instant::now();
         let dt = now.duration_since(last_tick).as_secs_f32().min(0.05);
         last_tick = now;
 
         while event::poll(Duration::from_millis(0))? {
-            if let Event::Key(key_event) = event::read()? {
-                match key_event.kind {
-                    KeyEventKind::Press | KeyEventKind::Repeat => {
-                        keys.insert(key_event.code);
-                    }
-                    KeyEventKind::Release => {
-                        keys.remove(&key_event.code);
-                    }
+            let key_event = match event::read()? {
+                Event::Key(key_event) => key_event,
+                Event::FocusLost => {
+                    // Releases that happen elsewhere never reach us
+                    keys.clear();
+                    game.focus_lost();
+                    continue;
+                }
+                Event::FocusGained => {
+                    game.focus_gained();
+                    continue;
                 }
-                
-                if game.config.key_matches(key_event.code, &game.config.keys.quit) {
-                    game.running = false;
+                _ => continue,
+            };
+
+            let key = KeyBinding::from_event(&key_event);
+            // Raw mode turns Ctrl+C and Ctrl+Z into ordinary key presses
+            if key_event.kind == KeyEventKind::Press && key.modifiers == KeyModifiers::CONTROL {
+                if key.code == KeyCode::Char('c') {
+                    game.screen = Screen::Quit;
+                    continue;
+                }
+                #[cfg(unix)]
+                if key.code == KeyCode::Char('z') {
+                    game.focus_lost();
+                    suspend()?;
+                    keys.clear();
+                    game.focus_gained();
+                    continue;
+                }
+            }
+            match key_event.kind {
+                KeyEventKind::Press => {
+                    keys.press(key);
+                    game.handle_key(key);
                 }
+                KeyEventKind::Repeat => keys.press(key),
+                KeyEventKind::Release => keys.release(key),
             }
         }
 
-        game.update(dt, &keys);
+        keys.configure(&game.config.input);
+        keys.tick(dt);
+        let held = game.actions.held(keys.iter());
+        game.update(dt, &held);
         game.draw(&mut stdout)?;
         
         let elapsed = now.elapsed();
@@ -444,15 +1411,8 @@ fn main() -> io::Result<()> {
         }
     }
 
-    let _ = execute!(stdout, PopKeyboardEnhancementFlags);
-    execute!(stdout, cursor::Show, LeaveAlternateScreen)?;
-    disable_raw_mode()?;
-
-    if game.won {
-        println!("CONGRATULATIONS! You escaped with the trophy!");
-    } else {
-        println!("GAME OVER: {}", game.message);
-    }
+    drop(guard);
+    println!("Thanks for playing Rusty Dave! Final score: {} (level {})", game.score, game.current_level);
 
     Ok(())
 }
@@ -460,6 +1420,7 @@ fn main() -> io::Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rustydave::{generate_level, Archetype, ConfigLayer};
 
     #[test]
     fn test_game_init_level() {
@@ -470,23 +1431,12 @@ mod tests {
 
     #[test]
     fn test_game_init_level_clamping() {
-        // We don't clamp in Game::new, we clamp in main (via parse_args).
+        // We don't clamp in Game::new, we clamp in main.
         // But let's check Game::new handles whatever it's given.
         let game = Game::new(10, Config::default(), false);
         assert_eq!(game.current_level, 10);
     }
 
-    #[test]
-    fn test_parse_args() {
-        let max = 10;
-        assert_eq!(parse_args(&vec!["exe".to_string()], max), (1, false));
-        assert_eq!(parse_args(&vec!["exe".to_string(), "3".to_string()], max), (3, false));
-        assert_eq!(parse_args(&vec!["exe".to_string(), "0".to_string()], max), (1, false));
-        assert_eq!(parse_args(&vec!["exe".to_string(), "20".to_string()], max), (max, false));
-        assert_eq!(parse_args(&vec!["exe".to_string(), "--ascii".to_string()], max), (1, true));
-        assert_eq!(parse_args(&vec!["exe".to_string(), "5".to_string(), "--ascii".to_string()], max), (5, true));
-    }
-
     #[test]
     fn test_config_default() {
         let config = Config::default();
@@ -495,18 +1445,19 @@ mod tests {
     }
 
     #[test]
-    fn test_key_matches() {
-        let config = Config::default();
-        assert!(config.key_matches(KeyCode::Left, &config.keys.left));
-        assert!(config.key_matches(KeyCode::Char('a'), &config.keys.left));
-        assert!(config.key_matches(KeyCode::Char('A'), &config.keys.left));
-        assert!(!config.key_matches(KeyCode::Right, &config.keys.left));
+    fn test_default_key_actions() {
+        let game = Game::new(1, Config::default(), false);
+        assert_eq!(game.actions.actions(KeyBinding::from(KeyCode::Left)), &[Action::Left]);
+        assert_eq!(game.actions.actions(KeyBinding::from(KeyCode::Char('a'))), &[Action::Left]);
+        assert_eq!(game.actions.actions(KeyBinding::from(KeyCode::Char('A'))), &[Action::Left]);
+        assert!(!game.actions.actions(KeyBinding::from(KeyCode::Right)).contains(&Action::Left));
     }
 
     #[test]
     fn test_diamond_collection() {
         let mut game = Game::new(1, Config::default(), false);
         game.start_timer = 0.0;
+        game.screen = Screen::Playing;
         game.score = 0;
         game.level[10][10] = Tile::Diamond;
         game.player.x = 10.0;
@@ -524,6 +1475,7 @@ mod tests {
     fn test_lives_decrement() {
         let mut game = Game::new(1, Config::default(), false);
         game.start_timer = 0.0;
+        game.screen = Screen::Playing;
         game.lives = 3;
         game.level[10][10] = Tile::Hazard;
         game.player.x = 10.0;
@@ -533,7 +1485,534 @@ mod tests {
         game.update(0.01, &keys);
         
         assert_eq!(game.lives, 2);
-        assert!(game.is_dead);
+        assert_eq!(game.screen, Screen::Dead);
+    }
+
+    #[test]
+    fn test_falling_into_a_pit_costs_a_life() {
+        let mut game = Game::new(1, Config::default(), false);
+        game.start_timer = 0.0;
+        game.screen = Screen::Playing;
+        game.lives = 3;
+        let bottom = LEVEL_HEIGHT - 1;
+        for x in 29..=31 {
+            game.level[bottom][x] = Tile::Empty;
+            game.level[bottom - 1][x] = Tile::Empty;
+        }
+        game.player.x = 30.5;
+        game.player.y = bottom as f32 - 1.5;
+
+        let keys = HashSet::new();
+        for _ in 0..100 {
+            game.update(0.01, &keys);
+            if game.screen != Screen::Playing {
+                break;
+            }
+        }
+        assert_eq!(game.lives, 2);
+        assert_eq!(game.screen, Screen::Dead);
+        assert!(game.message.contains("pit"), "{}", game.message);
+        // Dave is still drawn, in the pit he fell down
+        assert_eq!(game.player.y.floor() as usize, bottom);
+
+        // Pausing and resuming brings him back up rather than dropping him again
+        game.update(1.0, &keys);
+        game.handle_action(Action::Quit);
+        game.handle_action(Action::Restart);
+        for _ in 0..100 {
+            game.update(0.01, &keys);
+        }
+        assert_eq!(game.lives, 2);
+        assert_eq!(game.screen, Screen::Playing);
+    }
+
+    #[test]
+    fn test_pausing_on_the_death_screen_respawns() {
+        let mut game = Game::new(1, Config::default(), false);
+        game.start_timer = 0.0;
+        game.screen = Screen::Playing;
+        game.lives = 3;
+        game.level[10][10] = Tile::Hazard;
+        game.player.x = 10.0;
+        game.player.y = 10.0;
+        game.update(0.01, &HashSet::new());
+        assert_eq!(game.screen, Screen::Dead);
+
+        // Esc waits out the same delay as Enter
+        game.handle_action(Action::Quit);
+        assert_eq!(game.screen, Screen::Dead);
+        game.update(1.0, &HashSet::new());
+        game.handle_action(Action::Quit);
+        assert_eq!(game.screen, Screen::Paused);
+        game.handle_action(Action::Restart);
+        assert_eq!(game.screen, Screen::Playing);
+        for _ in 0..100 {
+            game.update(0.01, &HashSet::new());
+        }
+        assert_eq!(game.lives, 2);
+        assert_eq!(game.screen, Screen::Playing);
+    }
+
+    #[test]
+    fn test_platform_is_one_way() {
+        let mut game = Game::new(1, Config::default(), false);
+        game.start_timer = 0.0;
+        game.screen = Screen::Playing;
+        for y in 5..15 {
+            for x in 15..25 {
+                game.level[y][x] = Tile::Empty;
+            }
+        }
+        game.level[10][20] = Tile::Platform;
+
+        // Jumping up from below passes through the platform
+        game.player.x = 20.5;
+        game.player.y = 11.5;
+        game.player.vy = -30.0;
+        game.update(0.05, &HashSet::new());
+        assert!(game.player.y < 11.0);
+
+        // Falling from above lands on it
+        game.player.y = 9.5;
+        game.player.vy = 10.0;
+        game.update(0.05, &HashSet::new());
+        assert!(game.player.on_ground);
+        assert_eq!(game.player.y.floor(), 9.0);
+    }
+
+    #[test]
+    fn test_platform_drop_through() {
+        let mut game = Game::new(1, Config::default(), false);
+        game.start_timer = 0.0;
+        game.screen = Screen::Playing;
+        for y in 5..15 {
+            for x in 15..25 {
+                game.level[y][x] = Tile::Empty;
+            }
+        }
+        game.level[10][20] = Tile::Platform;
+        game.player.x = 20.5;
+        game.player.y = 9.99;
+        game.player.on_ground = true;
+
+        let held = [Action::Down, Action::Jump].into_iter().collect();
+        game.update(0.05, &held);
+        for _ in 0..3 {
+            game.update(0.05, &HashSet::new());
+        }
+        assert!(game.player.y > 11.0);
+    }
+
+    #[test]
+    fn test_key_opens_matching_door() {
+        let mut game = Game::new(1, Config::default(), false);
+        game.start_timer = 0.0;
+        game.screen = Screen::Playing;
+        game.level[10][10] = Tile::Key(KeyColor::Red);
+        game.level[10][11] = Tile::Door(KeyColor::Red);
+        game.level[10][12] = Tile::Door(KeyColor::Blue);
+        assert!(game.is_colliding(11.5, 10.5, 10.5));
+
+        game.player.x = 10.0;
+        game.player.y = 10.0;
+        game.update(0.01, &HashSet::new());
+
+        assert_eq!(game.player.inventory, vec![KeyColor::Red]);
+        assert_eq!(game.level[10][10], Tile::Empty);
+        assert!(!game.is_colliding(11.5, 10.5, 10.5));
+        assert!(game.is_colliding(12.5, 10.5, 10.5));
+    }
+
+    #[test]
+    fn test_crumbling_floor_collapses_and_respawns() {
+        let mut game = Game::new(1, Config::default(), false);
+        game.start_timer = 0.0;
+        game.screen = Screen::Playing;
+        for y in 5..15 {
+            for x in 15..25 {
+                game.level[y][x] = Tile::Empty;
+            }
+        }
+        game.level[11][20] = Tile::Crumbling;
+        game.player.x = 20.5;
+        game.player.y = 10.99;
+
+        game.update(0.01, &HashSet::new());
+        assert!(game.player.on_ground);
+        assert!(game.tile_timers.contains_key(&(20, 11)));
+
+        for _ in 0..(CRUMBLE_DELAY / 0.05) as usize + 1 {
+            game.update(0.05, &HashSet::new());
+        }
+        assert_eq!(game.level[11][20], Tile::Collapsed);
+        assert!(game.player.y > 11.0);
+
+        game.player.x = 17.5;
+        game.update_tile_timers(CRUMBLE_RESPAWN_TIME);
+        assert_eq!(game.level[11][20], Tile::Crumbling);
+    }
+
+    #[test]
+    fn test_fire_jet_cycles_and_kills() {
+        let mut game = Game::new(1, Config::default(), false);
+        game.start_timer = 0.0;
+        game.screen = Screen::Playing;
+        game.level[10][10] = Tile::FireJet { active: false };
+        game.tile_timers.insert((10, 10), 0.02);
+        game.player.x = 10.0;
+        game.player.y = 10.0;
+
+        game.update(0.01, &HashSet::new());
+        assert_eq!(game.screen, Screen::Playing);
+
+        game.player.x = 10.0;
+        game.player.y = 10.0;
+        game.update(0.01, &HashSet::new());
+        assert_eq!(game.level[10][10], Tile::FireJet { active: true });
+        assert_eq!(game.screen, Screen::Dead);
+    }
+
+    #[test]
+    fn test_spring_launches_dave() {
+        let mut game = Game::new(1, Config::default(), false);
+        game.start_timer = 0.0;
+        game.screen = Screen::Playing;
+        for y in 2..15 {
+            for x in 15..25 {
+                game.level[y][x] = Tile::Empty;
+            }
+        }
+        game.level[15][20] = Tile::Spring;
+        game.player.x = 20.5;
+        game.player.y = 14.95;
+        game.player.vy = 5.0;
+
+        game.update(0.02, &HashSet::new());
+        assert_eq!(game.player.vy, game.config.spring.vy);
+        for _ in 0..10 {
+            game.update(0.02, &HashSet::new());
+        }
+        assert!(game.player.y < 10.0);
+    }
+
+    #[test]
+    fn test_conveyor_and_ice_surfaces() {
+        let mut game = Game::new(1, Config::default(), false);
+        game.start_timer = 0.0;
+        game.screen = Screen::Playing;
+        for y in 5..15 {
+            for x in 10..40 {
+                game.level[y][x] = Tile::Empty;
+            }
+        }
+        for x in 10..20 {
+            game.level[11][x] = Tile::ConveyorRight;
+        }
+        for x in 25..40 {
+            game.level[11][x] = Tile::Ice;
+        }
+
+        // Standing still on a conveyor still moves Dave along it
+        game.player.x = 12.5;
+        game.player.y = 10.99;
+        game.player.on_ground = true;
+        game.update(0.05, &HashSet::new());
+        assert!(game.player.x > 12.5);
+
+        // Ice keeps most of Dave's speed when he stops pushing
+        game.player.x = 27.5;
+        game.player.y = 10.99;
+        game.player.vx = 20.0;
+        game.update(0.05, &HashSet::new());
+        let friction = game.config.physics.friction * game.config.ice.friction_mult;
+        assert_eq!(game.player.vx, 20.0 - friction * 0.05);
+    }
+
+    #[test]
+    fn test_teleporter_moves_dave_to_partner() {
+        let mut game = Game::new(1, Config::default(), false);
+        game.start_timer = 0.0;
+        game.screen = Screen::Playing;
+        game.level[10][10] = Tile::Teleporter(0);
+        game.level[5][40] = Tile::Teleporter(0);
+        game.player.x = 10.5;
+        game.player.y = 10.5;
+        game.player.vx = 5.0;
+
+        game.update(0.01, &HashSet::new());
+        assert_eq!((game.player.x.floor(), game.player.y.floor()), (40.0, 5.0));
+        assert_eq!(game.player.vx, 0.0);
+
+        // Arriving doesn't bounce Dave straight back
+        game.update(0.01, &HashSet::new());
+        assert_eq!(game.player.x.floor(), 40.0);
+    }
+
+    #[test]
+    fn test_warp_skips_levels() {
+        let mut game = Game::new(2, Config::default(), false);
+        game.start_timer = 0.0;
+        game.screen = Screen::Playing;
+        game.level[10][10] = Tile::Warp;
+        game.player.x = 10.5;
+        game.player.y = 10.5;
+
+        game.update(0.01, &HashSet::new());
+        assert_eq!(game.current_level, 2 + game.config.warp.skip_levels);
+        assert!(game.message.contains("WARP"));
+    }
+
+    #[test]
+    fn test_checkpoint_respawn_restores_progress() {
+        let mut game = Game::new(1, Config::default(), false);
+        game.start_timer = 0.0;
+        game.screen = Screen::Playing;
+        game.level[10][10] = Tile::Checkpoint;
+        game.level[10][30] = Tile::Diamond;
+        game.level[12][20] = Tile::Hazard;
+        game.player.has_trophy = true;
+        game.player.x = 10.5;
+        game.player.y = 10.5;
+        game.update(0.01, &HashSet::new());
+        assert!(game.checkpoint.is_some());
+
+        // Collect a diamond after the checkpoint, then die
+        game.player.x = 30.5;
+        game.player.y = 10.5;
+        game.update(0.01, &HashSet::new());
+        assert_eq!(game.score, 100);
+        // Standing on the same checkpoint again keeps the snapshot taken on arrival
+        game.player.x = 10.5;
+        game.player.y = 10.5;
+        game.update(0.01, &HashSet::new());
+        assert_eq!(game.checkpoint.as_ref().map(|c| c.score), Some(0));
+        game.player.x = 20.5;
+        game.player.y = 12.5;
+        game.update(0.01, &HashSet::new());
+        assert_eq!(game.screen, Screen::Dead);
+
+        game.update(1.0, &HashSet::new());
+        game.handle_action(Action::Restart);
+        assert_eq!(game.screen, Screen::Playing);
+        assert_eq!((game.player.x.floor(), game.player.y.floor()), (10.0, 10.0));
+        assert!(game.player.has_trophy);
+        assert_eq!(game.level[10][30], Tile::Diamond);
+        assert_eq!(game.score, 0);
+
+        // Briefly invulnerable after respawning
+        game.player.x = 20.5;
+        game.player.y = 12.5;
+        game.update(0.01, &HashSet::new());
+        assert_eq!(game.screen, Screen::Playing);
+    }
+
+    #[test]
+    fn test_restart_respawn_mode_regenerates_level() {
+        let config = Config { respawn: RespawnMode::Restart, ..Config::default() };
+        let mut game = Game::new(1, config, false);
+        game.start_timer = 0.0;
+        game.screen = Screen::Playing;
+        game.level[10][10] = Tile::Checkpoint;
+        game.player.has_trophy = true;
+        game.player.x = 10.5;
+        game.player.y = 10.5;
+        game.update(0.01, &HashSet::new());
+        game.screen = Screen::Dead;
+        game.lives = 2;
+
+        game.update(1.0, &HashSet::new());
+        game.handle_action(Action::Restart);
+        assert!(!game.player.has_trophy);
+        assert!(game.checkpoint.is_none());
+        assert_eq!(game.level[10][10], generate_level(1).0[10][10]);
     }
-}
 
+    #[test]
+    fn test_title_menu_starts_game() {
+        let mut game = Game::new(1, Config::default(), false);
+        assert_eq!(game.screen, Screen::Title);
+        game.handle_action(Action::Down);
+        assert_eq!(game.selected, 1);
+        game.handle_action(Action::Jump);
+        game.handle_action(Action::Restart);
+        assert_eq!(game.screen, Screen::Playing);
+    }
+
+    #[test]
+    fn test_pause_freezes_play() {
+        let mut game = Game::new(1, Config::default(), false);
+        game.start_timer = 0.0;
+        game.screen = Screen::Playing;
+        game.handle_action(Action::Pause);
+        assert_eq!(game.screen, Screen::Paused);
+
+        let (x, y) = (game.player.x, game.player.y);
+        game.player.vx = 10.0;
+        game.update(0.1, &HashSet::new());
+        assert_eq!((game.player.x, game.player.y), (x, y));
+
+        // Restart Level from the pause menu goes straight back into play
+        game.handle_action(Action::Down);
+        game.handle_action(Action::Restart);
+        assert_eq!(game.screen, Screen::Playing);
+        assert_eq!(game.player.vx, 0.0);
+    }
+
+    #[test]
+    fn test_game_over_and_level_complete_screens() {
+        let mut game = Game::new(1, Config::default(), false);
+        game.start_timer = 0.0;
+        game.screen = Screen::Playing;
+        game.lives = 1;
+        game.level[10][10] = Tile::Hazard;
+        game.player.x = 10.5;
+        game.player.y = 10.5;
+        game.update(0.01, &HashSet::new());
+        assert_eq!(game.screen, Screen::GameOver);
+        game.handle_action(Action::Restart);
+        assert_eq!(game.screen, Screen::Playing);
+        assert_eq!(game.lives, 3);
+
+        game.start_timer = 0.0;
+        game.level[10][10] = Tile::Exit;
+        game.player.has_trophy = true;
+        game.player.x = 10.5;
+        game.player.y = 10.5;
+        game.update(0.01, &HashSet::new());
+        assert_eq!(game.screen, Screen::LevelComplete);
+        game.handle_action(Action::Restart);
+        assert_eq!(game.screen, Screen::Playing);
+        assert_eq!(game.current_level, 2);
+    }
+
+    #[test]
+    fn test_options_edit_physics_and_rebind() {
+        let mut game = Game::new(1, Config::default(), false);
+        game.handle_action(Action::Down);
+        game.handle_action(Action::Restart);
+        assert_eq!(game.screen, Screen::Options);
+
+        // First row is target_vx
+        game.handle_action(Action::Right);
+        assert_eq!(game.config.physics.target_vx, 31.0);
+        game.handle_action(Action::Left);
+        game.handle_action(Action::Left);
+        assert_eq!(game.config.physics.target_vx, 29.0);
+
+        // Rebind "left" to x
+        game.selected = OPTION_ROWS.iter().position(|&r| r == OptionRow::Binding(Action::Left)).unwrap();
+        game.handle_action(Action::Restart);
+        assert_eq!(game.rebinding, Some(Action::Left));
+        game.handle_key(KeyBinding::from(KeyCode::Char('x')));
+        assert_eq!(game.config.keys.left, ["Left", "a", "A", "x"]);
+        assert!(game.rebinding.is_none());
+        // A key that is already bound isn't added twice
+        game.handle_action(Action::Restart);
+        game.handle_key(KeyBinding::from(KeyCode::Char('x')));
+        assert_eq!(game.config.keys.left.len(), 4);
+        assert_eq!(game.options_notice.as_deref(), Some("left is already bound to x."));
+
+        // The box scrolls so the highlighted row stays in view over the level
+        for selected in [0, OPTION_ROWS.len() - 1] {
+            game.selected = selected;
+            let lines = game.overlay_lines().unwrap();
+            assert!(lines.len() + 2 <= LEVEL_HEIGHT + 1, "{} lines", lines.len());
+            assert_eq!(lines.iter().filter(|line| line.starts_with('>')).count(), 1);
+        }
+        assert!(game.overlay_lines().unwrap().contains(&"> Back".to_string()));
+
+        game.handle_action(Action::Quit);
+        assert_eq!(game.screen, Screen::Title);
+    }
+
+    #[test]
+    fn test_presets() {
+        let easy = Config { preset: Preset::Easy, ..Config::default() };
+        let mut game = Game::new(1, easy, false);
+        assert_eq!(game.lives, 5);
+        assert_eq!(game.award(100), 50);
+        assert_eq!(game.score, 50);
+
+        // --lives wins over the preset when a new game starts
+        game.lives_override = Some(7);
+        game.lives = 0;
+        game.reset();
+        assert_eq!(game.lives, 7);
+
+        game.screen = Screen::Options;
+        game.selected = OPTION_ROWS.iter().position(|&r| r == OptionRow::Preset).unwrap();
+        game.handle_action(Action::Right);
+        assert_eq!(game.config.preset, Preset::Normal);
+        game.handle_action(Action::Left);
+        game.handle_action(Action::Left);
+        assert_eq!(game.config.preset, Preset::Hard);
+        assert_eq!(game.award(100), 200);
+
+        let hard = game.config.gen_params();
+        assert_eq!(hard.floor_hazard_chance, 45);
+        assert!(hard.difficulty.floor_hazard_chance <= 100);
+    }
+
+    #[test]
+    fn test_camera_follows_dave_up_a_tower() {
+        let mut config = Config::default();
+        config.generator.archetypes = vec![Archetype::Tower];
+        let mut game = Game::new(1, config, false);
+        let height = game.level.height();
+        assert_eq!(height, LEVEL_HEIGHT * game.config.generator.tower_screens as usize);
+        assert_eq!(game.camera_top(), height - LEVEL_HEIGHT);
+
+        game.player.y = (height / 2) as f32;
+        assert_eq!(game.camera_top(), height / 2 - LEVEL_HEIGHT / 2);
+        game.player.y = 3.0;
+        assert_eq!(game.camera_top(), 0);
+        assert!(!game.is_colliding(5.5, (height - 10) as f32, (height - 10) as f32));
+    }
+
+    #[test]
+    fn test_config_hot_reload() {
+        let path = std::env::temp_dir().join(format!("rustydave-reload-{}.toml", std::process::id()));
+        std::fs::write(&path, "[physics]\ngravity = 60.0\n").unwrap();
+        let mut game = Game::new(1, Config::default(), false);
+        // Only the temp file, so the result doesn't depend on the working directory
+        game.config_paths = ConfigPaths { layers: vec![ConfigLayer { path: path.clone(), required: true }] };
+
+        // The initial load hasn't been recorded, so the first poll picks the file up
+        game.update(CONFIG_POLL_INTERVAL, &HashSet::new());
+        assert_eq!(game.config.physics.gravity, 60.0);
+        assert_eq!(game.message, "Config reloaded.");
+
+        // A broken file keeps the old settings
+        std::fs::write(&path, "[physics]\ngravity = \n").unwrap();
+        game.config_mtimes.clear();
+        game.update(CONFIG_POLL_INTERVAL, &HashSet::new());
+        assert_eq!(game.config.physics.gravity, 60.0);
+        assert!(game.message.starts_with("Config not reloaded"), "{}", game.message);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_focus_loss_freezes_play() {
+        let mut game = Game::new(1, Config::default(), false);
+        game.start_timer = 0.0;
+        game.screen = Screen::Playing;
+        game.focus_lost();
+        assert_eq!(game.screen, Screen::FocusLost);
+        assert!(game.overlay_lines().is_none());
+
+        let (x, y) = (game.player.x, game.player.y);
+        game.update(0.5, &[Action::Right].into_iter().collect());
+        assert_eq!((game.player.x, game.player.y), (x, y));
+
+        game.focus_gained();
+        assert!(game.overlay_lines().is_some());
+        game.handle_key(KeyBinding::from(KeyCode::Char('z')));
+        assert_eq!(game.screen, Screen::Playing);
+
+        // Menus aren't interrupted
+        game.screen = Screen::Paused;
+        game.focus_lost();
+        assert_eq!(game.screen, Screen::Paused);
+    }
+}